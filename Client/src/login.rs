@@ -1,14 +1,24 @@
 use conrod;
 use ui::Ids;
 
-pub fn build_login<F>(ref mut ui: conrod::UiCell, ids: &Ids, username: &mut String, mut logged_in: F) where F: FnMut(&str) -> () {
+/**
+ * Text typed into the password box is shown as one * per character
+ */
+fn masked(password: &str) -> String {
+    password.chars().map(|_| '*').collect()
+}
+
+pub fn build_login<F, R>(ref mut ui: conrod::UiCell, ids: &Ids, username: &mut String, password: &mut String, mut logged_in: F, mut registered: R) where F: FnMut(&str, &str) -> (), R: FnMut(&str, &str) -> () {
     use conrod::{color, Labelable, widget, Colorable, Positionable, Scalar, Sizeable, Widget};
 
         // Our `Canvas` tree, upon which we will place our text widgets.
         widget::Canvas::new().flow_down(&[
             (ids.username_text_block, widget::Canvas::new().color(color::BLACK)),
             (ids.username_in, widget::Canvas::new().color(color::DARK_CHARCOAL)),
+            (ids.password_text_block, widget::Canvas::new().color(color::BLACK)),
+            (ids.password_in, widget::Canvas::new().color(color::DARK_CHARCOAL)),
             (ids.username_done, widget::Canvas::new().color(color::BLACK)),
+            (ids.register, widget::Canvas::new().color(color::BLACK)),
         ]).set(ids.master, ui);
 
         const PAD: Scalar = 20.0;
@@ -31,14 +41,48 @@ pub fn build_login<F>(ref mut ui: conrod::UiCell, ids: &Ids, username: &mut Stri
             	*username = edit;
             }
 
+        widget::Text::new("Enter Password")
+            .color(color::WHITE)
+            .padded_w_of(ids.password_text_block, PAD)
+            .middle_of(ids.password_text_block)
+            .align_text_middle()
+            .line_spacing(2.5)
+            .set(ids.password_text, ui);
+
+        //The box edits the masked text, each * still stands for the character it hides
+        for edit in widget::TextEdit::new(&masked(password))
+            .padded_w_of(ids.password_in, 20.0)
+            .mid_top_of(ids.password_in)
+            .align_text_x_middle()
+            .line_spacing(2.5)
+            .restrict_to_height(false)
+            .set(ids.password_in_block, ui) {
+                let kept = edit.chars().take_while(|&c| c == '*').count();
+                let typed: String = edit.chars().skip(kept).collect();
+                *password = password.chars().take(kept).chain(typed.chars()).collect();
+            }
+
+        let filled = !username.is_empty() && !password.is_empty();
+
         if widget::Button::new()
             .label("Done")
             .middle_of(ids.username_done)
             .color(color::TRANSPARENT)
             .label_color(color::WHITE)
             .set(ids.username_done_block, ui)
-            .was_clicked()
+            .was_clicked() && filled
+            {
+                logged_in(username, password);
+            }
+
+        if widget::Button::new()
+            .label("Register")
+            .middle_of(ids.register)
+            .color(color::TRANSPARENT)
+            .label_color(color::WHITE)
+            .set(ids.register_block, ui)
+            .was_clicked() && filled
             {
-                logged_in(username);
+                registered(username, password);
             }
 }
\ No newline at end of file
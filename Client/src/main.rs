@@ -17,15 +17,19 @@ mod net;
 mod sprite;
 
 use map::World;
+use std::collections::HashSet;
 use login::*;
-use graphics::math::{identity, Matrix2d};
+use graphics::math::multiply;
 
 use conrod::backend::piston::{self, Window, WindowEvents, OpenGL};
 use conrod::backend::piston::event::{UpdateEvent};
 use piston_window::{Texture, Flip, G2dTexture, TextureSettings};
-use piston_window::{PressEvent, MouseCursorEvent, MouseButton, clear, ReleaseEvent, Button, Key, G2d, Transformed};
-use world_lib::message::Message;
-use world_lib::entity::Entity;
+use piston_window::{PressEvent, MouseCursorEvent, MouseButton, clear, ReleaseEvent, Button, Key, G2d};
+use world_lib::message::{Message, Role};
+use world_lib::entity::{EntityID, Position, Body, WALK_SPEED};
+use world_lib::fov::{field_of_view, VIEW_RADIUS};
+use world_lib::map::{Tile, TileEdit};
+use world_lib::math::{Vec2, Transform2d};
 
 const WIDTH: u32 = 1080;
 const HEIGHT: u32 = 720;
@@ -49,13 +53,13 @@ fn main() {
     let image_map = conrod::image::Map::new();
 
     let mut user: String = "John Doe".to_string();
+    let mut password = String::new();
     let mut logged_in = false;
 
     let tiles = tileset::Tileset::new(&mut window, &assets::tiles(), "grass");
     let tex = Texture::from_path(&mut window.context.factory, &assets::image("male.png"), Flip::None, &TextureSettings::new()).unwrap();
 
-    let mut x_off = 0.0;
-    let mut y_off = 0.0;
+    let mut offset = Vec2::zero();
 
     let mut l_press = false;
     let mut r_press = false;
@@ -63,25 +67,29 @@ fn main() {
     let mut down = false;
     let mut zoom = false;
     let mut zoom_out = false;
+    let mut walk_up = false;
+    let mut walk_down = false;
+    let mut walk_left = false;
+    let mut walk_right = false;
     let mut scale = 1.0;
-    let mut cursor = (0.0, 0.0);
+    let mut cursor = Vec2::zero();
     
     let mut world: Option<World> = None;
+    let mut me: Option<EntityID> = None;
+    let mut role: Option<Role> = None;
+    let mut explored = HashSet::new();
     let mut conn = net::Connection::connect("127.0.0.1:15340");
 
-    fn build_transform(initial: Matrix2d, (x_off, y_off): (f64, f64), scale: f64) -> Matrix2d {
-        initial.scale(scale, scale).trans(-x_off, -y_off)
-    }
-
-    fn build_inverse(initial: Matrix2d, (x_off, y_off): (f64, f64), scale: f64) -> Matrix2d {
-        initial.trans(x_off, y_off).scale(scale, scale)
+    /* World to screen, scrolled by offset then zoomed */
+    fn camera(offset: Vec2, scale: f64) -> Transform2d {
+        Transform2d::identity().scale(Vec2::new(scale, scale)).translate(-offset)
     }
 
     // Poll events from the window.
     while let Some(event) = window.next_event(&mut events) {
 
         event.mouse_cursor(|x, y| {
-            cursor = (x,y);
+            cursor = Vec2::new(x, y);
         });
 
         if let Some(button) = event.press_args() {
@@ -98,6 +106,14 @@ fn main() {
                 zoom = true;
             } else if button == Button::Keyboard(Key::K) {
                 zoom_out = true;
+            } else if button == Button::Keyboard(Key::W) {
+                walk_up = true;
+            } else if button == Button::Keyboard(Key::S) {
+                walk_down = true;
+            } else if button == Button::Keyboard(Key::A) {
+                walk_left = true;
+            } else if button == Button::Keyboard(Key::D) {
+                walk_right = true;
             }
 
         } else if let Some(button) = event.release_args() {
@@ -126,32 +142,57 @@ fn main() {
                 zoom_out = false;
             }
 
+            if button == Button::Keyboard(Key::W) {
+                walk_up = false;
+            }
+
+            if button == Button::Keyboard(Key::S) {
+                walk_down = false;
+            }
+
+            if button == Button::Keyboard(Key::A) {
+                walk_left = false;
+            }
+
+            if button == Button::Keyboard(Key::D) {
+                walk_right = false;
+            }
+
             if button == Button::Mouse(MouseButton::Left) {
                 match world {
-                    Some(ref mut world) => {
-                        let (x, y) = map::get_elem(&world.map, cursor, build_inverse(identity(), (x_off, y_off), scale));
-                        println!("{} {}", x, y);
-                        if x < world.map.width && y < world.map.height {
-                            let idx = world.map.idx(x,y);
-                            world.map.layers[0][idx].y = 1;
+                    Some(ref world) => {
+                        if let Some((x, y)) = map::get_elem(&world.map, cursor, &camera(offset, scale)) {
+                            let edit = Message::SetTile(TileEdit { layer: 0, x: x, y: y, tile: Tile { x: 0, y: 1 } });
+
+                            //Players can't edit the map, the server would only refuse it
+                            if role.map(|role| role.can(&edit)).unwrap_or(false) {
+                                conn.send(&edit);
+                            }
                         }
-                        conn.send(&Message::Map(world.map.as_json()));
                     },
                     _ => {}
                 }
             }
+
+            if button == Button::Mouse(MouseButton::Right) {
+                if let Some(ref world) = world {
+                    if let Some((x, y)) = map::get_elem(&world.map, cursor, &camera(offset, scale)) {
+                        conn.send(&Message::MoveTo(x, y));
+                    }
+                }
+            }
         }
 
         if l_press {
-            x_off -= 1.0;
+            offset.x -= 1.0;
         } else if r_press {
-            x_off += 1.0;
+            offset.x += 1.0;
         }
 
         if up {
-            y_off -= 1.0;
+            offset.y -= 1.0;
         } else if down {
-            y_off += 1.0;
+            offset.y += 1.0;
         }
 
         if zoom {
@@ -169,14 +210,47 @@ fn main() {
             ui.handle_event(e);
         }
 
-        event.update(|_| {
+        event.update(|args| {
         	if !logged_in {
-        		build_login(ui.set_widgets(), &ids, &mut user, |username| {
-                    logged_in = true;
-                    conn.login(username, "test");
+        		let mut login = None;
+        		let mut register = None;
+        		build_login(ui.set_widgets(), &ids, &mut user, &mut password, |username, password| {
+                    login = Some((username.to_string(), password.to_string()));
+                }, |username, password| {
+                    register = Some((username.to_string(), password.to_string()));
                 });
+                if let Some((username, password)) = login {
+                    logged_in = true;
+                    conn.login(&username, &password);
+                } else if let Some((username, password)) = register {
+                    logged_in = true;
+                    conn.register(&username, &password);
+                }
         	} else {
         		noui::no_ui(ui.set_widgets(), &ids);
+
+                //Ask the server to move us, it has the final say on where we end up
+                let step = WALK_SPEED * args.dt;
+                let mut delta = Vec2::zero();
+
+                if walk_up {
+                    delta.y -= step;
+                } else if walk_down {
+                    delta.y += step;
+                }
+
+                if walk_left {
+                    delta.x -= step;
+                } else if walk_right {
+                    delta.x += step;
+                }
+
+                //Diagonals are no faster than walking straight
+                delta = delta.normalize() * step;
+
+                if !delta.is_zero() {
+                    conn.send(&Message::Move(delta));
+                }
         	}
         });
 
@@ -186,18 +260,63 @@ fn main() {
                     &Message::Say(ref text) => {
                         println!("{}", text);
                     },
+                    &Message::Kill(ref reason) => {
+                        println!("Disconnected: {}", reason);
+                    },
+                    &Message::Error(ref reason) => {
+                        println!("Error: {}", reason);
+                    },
                     &Message::World(ref data) => {
                         println!("Loading world from WorldData");
-                        world = Some(World::from_json(data));
+                        world = Some(data.clone());
+                        explored.clear();
                     },
-                    &Message::Entity(ref data) => {
+                    &Message::Entity(ref entity) => {
                         println!("Received Entity Data");
-                        let entity = Entity::from_json(data);
                         if let Some(ref mut world) = world {
                             println!("Updating Entity {:?}", entity);
-                            world.update_or_insert(&entity);
+                            world.update_or_insert(entity);
+                        }
+                    },
+                    &Message::Entities(ref entities) => {
+                        if let Some(ref mut world) = world {
+                            for entity in entities {
+                                world.update_or_insert(entity);
+                            }
+                        }
+                    },
+                    &Message::SetTile(ref edit) => {
+                        if let Some(ref mut world) = world {
+                            if let Err(e) = world.map.apply(edit) {
+                                println!("Could not apply tile edit {:?}, {:?}", edit, e);
+                            }
+                        }
+                    },
+                    &Message::SetTiles(ref edits) => {
+                        if let Some(ref mut world) = world {
+                            if let Err(e) = world.map.apply_all(edits) {
+                                println!("Could not apply {} tile edits, {:?}", edits.len(), e);
+                            }
+                        }
+                    },
+                    &Message::Components(ref updates) => {
+                        if let Some(ref mut world) = world {
+                            for &(id, ref component) in updates {
+                                world.apply(id, component.clone());
+                            }
+                        }
+                    },
+                    &Message::RemoveComponent(id, kind) => {
+                        if let Some(ref mut world) = world {
+                            world.remove_kind(id, kind);
                         }
                     },
+                    &Message::Control(id) => {
+                        me = Some(id);
+                    },
+                    &Message::Role(given) => {
+                        role = Some(given);
+                    },
                     &Message::RemoveEntity(id) => {
                         println!("Removing Entity Request");
                         if let Some(ref mut world) = world {
@@ -218,10 +337,27 @@ fn main() {
 
             match world {
                 Some(ref world) => {
-                    let view_transform = build_transform(c.transform, (x_off, y_off), scale);
-                    map::draw(&world.map, &tiles, view_transform, g);
-                    for entity in &world.entities {
-                        sprite::draw(entity, &tex, view_transform, g)
+                    let view_transform = multiply(c.transform, camera(offset, scale).as_matrix());
+
+                    //Until we know where we are everything is visible
+                    let visible = me.and_then(|id| world.tile(id)).map(|tile| field_of_view(&world.map, tile, VIEW_RADIUS));
+
+                    if let Some(ref visible) = visible {
+                        explored.extend(visible.iter().cloned());
+                    }
+
+                    map::draw(&world.map, &tiles, visible.as_ref().map(|visible| (visible, &explored)), view_transform, g);
+
+                    for (id, position, body) in world.join::<Position, Body>() {
+                        let seen = match (&visible, world.tile(id)) {
+                            (&Some(ref visible), Some(tile)) => visible.contains(&tile),
+                            (&Some(_), None) => false,
+                            (&None, _) => true
+                        };
+
+                        if seen {
+                            sprite::draw(position.pos, body.size, &tex, view_transform, g)
+                        }
                     }
                 },
                 None => { /* No map to draw */ }
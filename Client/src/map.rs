@@ -3,36 +3,43 @@ use graphics::{Image};
 use graphics::math::Matrix2d;
 use conrod::backend::piston::gfx::{Transformed, G2d};
 use std::default::Default;
+use std::collections::HashSet;
 pub use world_lib::{World, Map};
+use world_lib::math::{Vec2, Transform2d};
 
-pub fn draw(map: &Map, tiles: &Tileset, trans: Matrix2d, g: &mut G2d) {
+/**
+ * Tiles explored but not currently in view are drawn darkened
+ */
+const REMEMBERED: [f32; 4] = [0.35, 0.35, 0.45, 1.0];
+
+/**
+ * Draw the map, when fog is given only tiles that are visible or explored are drawn
+ */
+pub fn draw(map: &Map, tiles: &Tileset, fog: Option<(&HashSet<(usize, usize)>, &HashSet<(usize, usize)>)>, trans: Matrix2d, g: &mut G2d) {
 	let image = Image::new().rect([0.0, 0.0, 64.0, 32.0]);
 	for layer in &map.layers {
 		for y in 0..map.height {
 			for x in 0..map.width {
-	    		let l_x = if y % 2 == 0 { (x as f64) * map.tile_width } else { (map.tile_width / 2.0) + (x as f64 * map.tile_width) };
-		   		let l_y = (y as f64) * map.tile_height;
-		   		let tile = layer[map.idx(x, y)];
-				image.src_rect(tiles.src_map(tile.x, tile.y)).draw(&tiles.texture, &Default::default(), trans.trans(l_x, l_y), g);
+				let image = match fog {
+					Some((visible, _)) if visible.contains(&(x, y)) => image,
+					Some((_, explored)) if explored.contains(&(x, y)) => image.color(REMEMBERED),
+					Some(_) => continue,
+					None => image
+				};
+				let origin = map.tile_origin(x, y);
+				let tile = match layer[map.idx(x, y)] {
+					Some(tile) => tile,
+					None => continue
+				};
+				image.src_rect(tiles.src_map(tile.x, tile.y)).draw(&tiles.texture, &Default::default(), trans.trans(origin.x, origin.y), g);
     		}
     	}
    	}
 }
 
-pub fn get_elem(map: &Map, (x, y): (f64, f64), trans: Matrix2d) -> (usize, usize) {
-	use graphics::math::transform_vec;
-	let transformed = transform_vec(trans, [x, y]);
-	let (mut x, y) = (transformed[0], transformed[1]);
-
-	//Calculate y for the x offset
-	let y = (y / map.tile_height) as usize;
-		
-	//Deal with the ridged offset
-	if y % 2 == 0 {
-		x -= map.tile_width / 2.0;
-	}
-
-	let x = (x / map.tile_width) as usize;
-
-	(x, y)
-}
\ No newline at end of file
+/**
+ * The tile under a point on screen, None if nothing was clicked
+ */
+pub fn get_elem(map: &Map, cursor: Vec2, camera: &Transform2d) -> Option<(usize, usize)> {
+	camera.inverse().and_then(|screen_to_world| map.world_to_tile(screen_to_world.apply(cursor)))
+}
@@ -1,10 +1,12 @@
 use std::net::TcpStream;
 use std::io::{Read, Write};
-use world_lib::message::{Message, next};
+use world_lib::message::{Message, Capabilities, encode, next};
+use world_lib::message::protocol::{PROTOCOL_VERSION, SUPPORTED_CAPABILITIES};
 use std::io;
 
 pub struct Connection {
 	pub stream: TcpStream,
+	pub capabilities: Capabilities,
 	buffer: Vec<u8>
 }
 
@@ -13,9 +15,11 @@ impl Connection {
 	fn handle_buffer(&mut self) -> io::Result<Vec<Message>> {
 		let mut buffer = Vec::new();
 
-		while let Some((msg, remain)) = try!(next(&self.buffer)) {
+		while let Some(msg) = try!(next(&mut self.buffer)) {
+			if let Message::Welcome(_, capabilities) = msg {
+				self.capabilities = capabilities;
+			}
 			buffer.push(msg);
-			self.buffer = remain;
 		}
 
 		Ok(buffer)
@@ -24,7 +28,7 @@ impl Connection {
 	fn buffer_self(&mut self) -> io::Result<Vec<Message>> {
 		let mut buf: [u8; 4096] = [0; 4096];
 		let size = try!(self.stream.read(&mut buf));
-		self.buffer = self.buffer.iter().chain(&buf[0..size]).map(|&x| x).collect();
+		self.buffer.extend_from_slice(&buf[0..size]);
 		self.handle_buffer()
 	}
 
@@ -35,19 +39,27 @@ impl Connection {
 	}
 
 	pub fn send(&mut self, message: &Message) -> io::Result<()> {
-		write!(self.stream, "{}\0", message.as_json())
+		let frame = try!(encode(message));
+		self.stream.write_all(&frame)
 	}
 
 	pub fn login(&mut self, username: &str, password: &str) -> io::Result<()> {
 		self.send(&Message::Login(username.to_string(), password.to_string()))
 	}
 
+	pub fn register(&mut self, username: &str, password: &str) -> io::Result<()> {
+		self.send(&Message::Register(username.to_string(), password.to_string()))
+	}
+
 	pub fn connect(server: &str) -> Connection {
 		let stream = TcpStream::connect(server).unwrap();
 		stream.set_nonblocking(true);
-		Connection {
+		let mut conn = Connection {
 			stream: stream,
+			capabilities: 0,
 			buffer: Vec::new()
-		}
+		};
+		conn.send(&Message::Hello(PROTOCOL_VERSION, SUPPORTED_CAPABILITIES)).unwrap();
+		conn
 	}
 }
\ No newline at end of file
@@ -38,6 +38,6 @@ impl Tileset {
 	}
 
 	pub fn src_map(&self, x: usize, y: usize) -> [f64; 4] {
-		[(x * self.info.tile_height) as f64, (y * self.info.tile_height) as f64, self.info.tile_width as f64, self.info.tile_height as f64]
+		[(x * self.info.tile_width) as f64, (y * self.info.tile_height) as f64, self.info.tile_width as f64, self.info.tile_height as f64]
 	}
 }
\ No newline at end of file
@@ -5,8 +5,14 @@ widget_ids!{
         username_text,
         username_in_block,
         username_in,
+        password_text_block,
+        password_text,
+        password_in_block,
+        password_in,
         username_done_text,
         username_done_block,
-        username_done
+        username_done,
+        register_block,
+        register
     }
 }
\ No newline at end of file
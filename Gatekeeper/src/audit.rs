@@ -0,0 +1,50 @@
+use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * Append only record of privileged and refused actions
+ */
+pub struct AuditLog {
+    file: Option<File>
+}
+
+impl AuditLog {
+
+    /**
+     * An audit log that only goes to the logger
+     */
+    pub fn new() -> AuditLog {
+        AuditLog {
+            file: None
+        }
+    }
+
+    pub fn open(path: &Path) -> AuditLog {
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => AuditLog { file: Some(file) },
+            Err(e) => {
+                error!("Failed to open audit log {:?}, {:?}", path, e);
+                AuditLog::new()
+            }
+        }
+    }
+
+    pub fn record(&mut self, user: &str, action: &str, allowed: bool) {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+        let outcome = if allowed { "allowed" } else { "denied" };
+        let line = format!("{} {} {} {}", time, user, action, outcome);
+
+        info!(target: "audit", "{}", line);
+
+        let failed = match self.file {
+            Some(ref mut file) => writeln!(file, "{}", line).is_err(),
+            None => false
+        };
+
+        if failed {
+            error!("Failed to write to the audit log");
+        }
+    }
+}
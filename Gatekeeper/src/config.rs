@@ -0,0 +1,163 @@
+use std::env;
+use std::path::PathBuf;
+use std::cmp;
+use world_lib::map::{Generator, MAX_MAP_SIZE};
+
+/**
+ * Above this a tick would be shorter than the one millisecond timers can be set to
+ */
+const MAX_TICK_RATE: u64 = 1000;
+
+/**
+ * A zone whose map is generated from a seed rather than loaded from a file
+ */
+pub struct GeneratedMap {
+    pub zone: String,
+    pub generator: Generator,
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64
+}
+
+pub struct Config {
+    pub data_dir: PathBuf,
+    pub ephemeral: bool,
+    pub tick_rate: u64,
+    pub snapshot_interval: u64,
+    pub shutdown_countdown: u64,
+    pub tiles: PathBuf,
+
+    //Each map is hosted as a zone named after its file, players start in the first
+    pub maps: Vec<PathBuf>,
+
+    //Hosted after the maps, each as its own zone
+    pub generated: Vec<GeneratedMap>,
+
+    //Save this zone's map for Tiled to the path and exit instead of serving
+    pub export_map: Option<(String, PathBuf)>
+}
+
+impl Config {
+    pub fn default() -> Config {
+        Config {
+            data_dir: PathBuf::from("data"),
+            ephemeral: false,
+            tick_rate: 20,
+            snapshot_interval: 60,
+            shutdown_countdown: 0,
+            tiles: PathBuf::from("../Client/assets/images/tiles/grass.tiles"),
+            maps: Vec::new(),
+            generated: Vec::new(),
+            export_map: None
+        }
+    }
+
+    /**
+     * Parse the command line, anything not recognised is logged and ignored
+     */
+    pub fn from_args() -> Config {
+        let mut config = Config::default();
+        let mut args = env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_ref() {
+                "--data" => match args.next() {
+                    Some(dir) => config.data_dir = PathBuf::from(dir),
+                    None => warn!("--data expects a directory")
+                },
+                "--ephemeral" => config.ephemeral = true,
+                "--tick-rate" => match args.next().and_then(|rate| rate.parse().ok()) {
+                    Some(rate) if rate > 0 && rate <= MAX_TICK_RATE => config.tick_rate = rate,
+                    _ => warn!("--tick-rate expects a number of ticks per second from 1 to {}", MAX_TICK_RATE)
+                },
+                "--snapshot-interval" => match args.next().and_then(|secs| secs.parse().ok()) {
+                    Some(secs) if secs > 0 => config.snapshot_interval = secs,
+                    _ => warn!("--snapshot-interval expects a number of seconds")
+                },
+                "--shutdown-countdown" => match args.next().and_then(|secs| secs.parse().ok()) {
+                    Some(secs) => config.shutdown_countdown = secs,
+                    _ => warn!("--shutdown-countdown expects a number of seconds")
+                },
+                "--tiles" => match args.next() {
+                    Some(path) => config.tiles = PathBuf::from(path),
+                    None => warn!("--tiles expects a tile definition file")
+                },
+                "--map" => match args.next() {
+                    Some(path) => config.maps.push(PathBuf::from(path)),
+                    None => warn!("--map expects a map saved from Tiled as JSON")
+                },
+                "--generate" => match generated(&mut args) {
+                    Some(map) => config.generated.push(map),
+                    None => warn!("--generate expects a zone name, terrain, caves or dungeon, a width and height from 1 to {} and a seed", MAX_MAP_SIZE)
+                },
+                "--export-map" => match (args.next(), args.next()) {
+                    (Some(zone), Some(path)) => config.export_map = Some((zone, PathBuf::from(path))),
+                    _ => warn!("--export-map expects a zone name and a path to save its map to")
+                },
+                _ => warn!("Unknown argument {}", arg)
+            }
+        }
+
+        config
+    }
+
+    /**
+     * The time between simulation ticks in milliseconds
+     */
+    pub fn tick_ms(&self) -> u64 {
+        1000 / self.tick_rate
+    }
+
+    /**
+     * How often the event loop checks its timers. Timeouts fire on the first
+     * timer tick after they are due, so this must be well below tick_ms for
+     * ticks to run on time.
+     */
+    pub fn timer_tick_ms(&self) -> u64 {
+        cmp::max(self.tick_ms() / 10, 1)
+    }
+
+    pub fn snapshot_ms(&self) -> u64 {
+        self.snapshot_interval * 1000
+    }
+
+    /**
+     * Where every zone's world is saved between runs
+     */
+    pub fn snapshot(&self) -> PathBuf {
+        self.data_dir.join("world.json")
+    }
+
+    pub fn audit_log(&self) -> PathBuf {
+        self.data_dir.join("audit.log")
+    }
+
+    pub fn users_dir(&self) -> PathBuf {
+        self.data_dir.join("users")
+    }
+}
+
+/**
+ * The arguments to --generate, always takes all five so a bad one doesn't
+ * leave the rest to be read as other options
+ */
+fn generated<I: Iterator<Item = String>>(args: &mut I) -> Option<GeneratedMap> {
+    let found: Vec<String> = args.take(5).collect();
+
+    if found.len() < 5 {
+        return None;
+    }
+
+    let size = |arg: &str| arg.parse().ok().and_then(|size| if size > 0 && size <= MAX_MAP_SIZE { Some(size) } else { None });
+
+    match (Generator::from_name(&found[1]), size(&found[2]), size(&found[3]), found[4].parse().ok()) {
+        (Some(generator), Some(width), Some(height), Some(seed)) => Some(GeneratedMap {
+            zone: found[0].clone(),
+            generator: generator,
+            width: width,
+            height: height,
+            seed: seed
+        }),
+        _ => None
+    }
+}
@@ -4,17 +4,44 @@ use mio::tcp::*;
 use std::io::Result;
 use std::io::{Read, Write};
 use std::io::{Error, ErrorKind};
-use std::collections::VecDeque;
+use std::collections::{VecDeque, HashSet};
+use std::time::Instant;
 use user::User;
-use world_lib::entity::{null_id, EntityID};
+use world_lib::entity::EntityID;
+use world_lib::message::Capabilities;
 use server::Server;
 
+/**
+ * Where a connection is in the Hello -> Login -> Playing handshake
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    AwaitingHello,
+    AwaitingLogin,
+
+    //Waiting on the password check with this ticket
+    Authenticating(u64),
+
+    Playing
+}
+
 pub struct Connection {
-    pub user: User,
-    pub handshake_done: bool,
+    pub user: Option<User>,
+    pub state: State,
+    pub capabilities: Capabilities,
     pub token: Token,
     pub buffer: Vec<u8>,
-    pub entity: EntityID,
+    pub entity: Option<EntityID>,
+    pub zone: usize,
+    pub last_move: Instant,
+
+    //World units the player may still move, topped up as time passes
+    pub move_budget: f64,
+
+    //When a route was last found for the player, None if never
+    pub last_route: Option<Instant>,
+
+    pub visible: HashSet<EntityID>,
     sock: TcpStream,
     interest: EventSet,
     send_queue: VecDeque<Vec<u8>>,
@@ -23,14 +50,20 @@ pub struct Connection {
 impl Connection {
     pub fn new(sock: TcpStream, token: Token) -> Connection {
         Connection {
-            user: User::load("Anon", 0),
+            user: None,
             sock: sock,
             token: token,
             interest: EventSet::hup(),
             send_queue: VecDeque::new(),
             buffer: Vec::new(),
-            handshake_done: false,
-            entity: null_id()
+            state: State::AwaitingHello,
+            capabilities: 0,
+            entity: None,
+            zone: 0,
+            last_move: Instant::now(),
+            move_budget: 0.0,
+            last_route: None,
+            visible: HashSet::new()
         }
     }
 
@@ -49,39 +82,63 @@ impl Connection {
         }
     }
 
-    pub fn write_one(&mut self) -> Result<()> {
-        try!(self.send_queue.pop_front()
-            .ok_or(Error::new(ErrorKind::Other, "Could not pop send queue"))
-            .and_then(|buf| {
-                match self.sock.write(&buf) {
-                    Ok(n) => {
-                        debug!("CONN : we wrote {} bytes", n);
-                        Ok(())
-                    },
-                    Err(e) => {
-                        error!("Failed to send buffer for {:?}, error: {}", self.token, e);
-                        Err(e)
-                    }
+    /**
+     * Write as much of the first queued message as the socket will take. Anything
+     * left stays at the front of the queue to finish on the next writable event,
+     * so frames are never cut short. False if the socket couldn't take it all.
+     */
+    pub fn write_one(&mut self) -> Result<bool> {
+        let (written, len) = {
+            let buf = try!(self.send_queue.front().ok_or(Error::new(ErrorKind::Other, "Could not pop send queue")));
+
+            match self.sock.write(buf) {
+                Ok(n) => (n, buf.len()),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => (0, buf.len()),
+                Err(e) => {
+                    error!("Failed to send buffer for {:?}, error: {}", self.token, e);
+                    return Err(e);
                 }
-            })
-        );
+            }
+        };
+
+        debug!("CONN : we wrote {} of {} bytes", written, len);
+
+        if written < len {
+            if let Some(buf) = self.send_queue.front_mut() {
+                buf.drain(..written);
+            }
+
+            return Ok(false);
+        }
+
+        self.send_queue.pop_front();
 
         if self.send_queue.is_empty() {
             self.interest.remove(EventSet::writable());
         }
 
-        Ok(())
+        Ok(true)
     }
 
+    /**
+     * Write until the queue is empty or the socket is full
+     */
     pub fn write_remaining(&mut self) -> Result<()> {
         while self.interest.is_writable() {
-            if let Err(msg) = self.write_one() {
-                return Err(msg);
+            if !try!(self.write_one()) {
+                break;
             }
         }
         Ok(())
     }
 
+    /**
+     * Has everything queued been written?
+     */
+    pub fn is_drained(&self) -> bool {
+        self.send_queue.is_empty()
+    }
+
     pub fn shutdown(&mut self) -> Result<()> {
         self.sock.shutdown(Shutdown::Both)
     }
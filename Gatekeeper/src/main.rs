@@ -4,37 +4,197 @@ extern crate world_lib;
 #[macro_use] extern crate log;
 extern crate env_logger;
 extern crate rustc_serialize;
+extern crate crypto;
+extern crate chan_signal;
 
 mod user;
+mod config;
 mod connection;
 mod server;
+mod metrics;
+mod audit;
+mod zone;
+mod snapshot;
 
 use std::net::SocketAddr;
 use std::str::FromStr;
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use std::path::Path;
+use std::thread;
+use std::process;
 
 use mio::*;
 use mio::tcp::*;
 
-use server::Server;
+use server::{Server, Notice};
+use user::{Accounts, Storage, FileStorage, MemoryStorage};
+use config::Config;
+use audit::AuditLog;
+use zone::Zone;
 
-use world_lib::{World, Map};
+use chan_signal::Signal;
+
+use world_lib::Map;
+use world_lib::map::TileRegistry;
+use world_lib::utils::write_atomic;
 
 fn main() {
 
     env_logger::init().ok().expect("Failed to init logger");
 
+    //Has to happen before any other thread starts so they all leave these signals to us
+    let signals = chan_signal::notify(&[Signal::INT, Signal::TERM]);
+
+    let config = Config::from_args();
+
+    //Zones saved last run are kept as they were left, the maps only seed new zones
+    let mut zones: Vec<Zone> = if config.ephemeral {
+        Vec::new()
+    } else {
+        match snapshot::load(&config.snapshot()).expect("Failed to load the world snapshot") {
+            Some(snapshot) => snapshot.into_zones().expect("Failed to restore the world snapshot"),
+            None => Vec::new()
+        }
+    };
+
+    for path in &config.maps {
+        let name = zone_name(path);
+
+        if !zones.iter().any(|zone| zone.name == name) {
+            zones.push(Zone::new(&name, load_map(path).expect("Failed to load a map")));
+        }
+    }
+
+    for generated in &config.generated {
+        if !zones.iter().any(|zone| zone.name == generated.zone) {
+            let map = Map::generate(generated.generator, generated.width, generated.height, generated.seed);
+            zones.push(Zone::new(&generated.zone, map));
+        }
+    }
+
+    if zones.is_empty() {
+        zones.push(Zone::new("default", Map::new(16, 32)));
+    }
+
+    match TileRegistry::load(&config.tiles) {
+        Ok(registry) => for zone in zones.iter_mut() {
+            zone.world.map.registry = registry.clone();
+        },
+        Err(e) => warn!("Failed to load tile definitions from {:?} ({}), every tile will be walkable", config.tiles, e)
+    }
+
+    check_portals(&zones);
+
+    if let Some((ref zone, ref path)) = config.export_map {
+        match export_map(&zones, zone, path) {
+            Ok(()) => info!("Saved the map for zone {} to {:?}", zone, path),
+            Err(e) => {
+                error!("Failed to export the map for zone {}, {}", zone, e);
+                process::exit(1);
+            }
+        }
+
+        return;
+    }
+
     let addr: SocketAddr = FromStr::from_str("127.0.0.1:15340")
         .ok().expect("Failed to parse host:port string");
 
     let sock = TcpListener::bind(&addr).ok().expect("Failed to bind address");
 
-    let mut event_loop = EventLoop::new().ok().expect("Failed to create event loop");
+    //The default timer tick of 100ms would hold every tick back to that
+    let mut loop_config = EventLoopConfig::new();
+    loop_config.timer_tick_ms(config.timer_tick_ms());
 
-    let world = World::new(Map::new(16, 32));
+    let mut event_loop = EventLoop::configured(loop_config).ok().expect("Failed to create event loop");
 
-    let mut server = Server::new(sock, world);
+    let storage: Box<Storage> = if config.ephemeral {
+        warn!("Running with ephemeral storage, accounts will not be saved");
+        Box::new(MemoryStorage::new())
+    } else {
+        Box::new(FileStorage::new(&config.users_dir()).ok().expect("Failed to open user storage"))
+    };
+
+    let audit = if config.ephemeral {
+        AuditLog::new()
+    } else {
+        AuditLog::open(&config.audit_log())
+    };
+
+    let mut server = Server::new(sock, zones, Accounts::new(storage), audit, config.tick_ms(), config.shutdown_countdown);
     server.register(&mut event_loop).ok().expect("Failed to register server with event loop");
+    server.start_authenticator(&mut event_loop);
+    server.start_ticking(&mut event_loop).ok().expect("Failed to schedule the first tick");
+
+    if !config.ephemeral {
+        server.start_snapshots(&mut event_loop, config.snapshot(), config.snapshot_ms()).ok().expect("Failed to schedule snapshots");
+    }
+
+    let notices = event_loop.channel();
+
+    thread::spawn(move || {
+        if let Some(signal) = signals.recv() {
+            info!("Received {:?}", signal);
+
+            if notices.send(Notice::Shutdown).is_err() {
+                error!("Failed to tell the server to shut down");
+            }
+        }
+
+        //Asked twice, don't wait for the countdown or the save
+        if let Some(signal) = signals.recv() {
+            warn!("Received {:?} again, exiting now", signal);
+            process::exit(1);
+        }
+    });
 
     info!("Even loop starting...");
     event_loop.run(&mut server).ok().expect("Failed to start event loop");
-}
\ No newline at end of file
+
+    server.finish();
+}
+
+/**
+ * Load a map made in Tiled
+ */
+fn load_map(path: &Path) -> io::Result<Map> {
+    let mut data = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut data));
+    Map::from_tiled(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))
+}
+
+/**
+ * Save a zone's map as Tiled JSON so builders can keep editing it in Tiled
+ */
+fn export_map(zones: &[Zone], name: &str, path: &Path) -> io::Result<()> {
+    let zone = try!(zones.iter().find(|zone| zone.name == name)
+        .ok_or(io::Error::new(io::ErrorKind::NotFound, format!("no zone named {}", name))));
+    let data = try!(zone.world.map.as_tiled().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))));
+    write_atomic(path, data.as_bytes())
+}
+
+/**
+ * Zones are named after their map file, which is what portals refer to
+ */
+fn zone_name(path: &Path) -> String {
+    path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("default").to_string()
+}
+
+/**
+ * Warn about portals that lead nowhere or straight into another portal
+ */
+fn check_portals(zones: &[Zone]) {
+    for zone in zones {
+        for portal in &zone.world.map.portals {
+            match zones.iter().find(|other| other.name == portal.zone) {
+                Some(other) if other.world.map.portal_at(portal.target).is_some() => {
+                    warn!("A portal in {} leads onto another portal in {}", zone.name, portal.zone)
+                },
+                Some(_) => {},
+                None => warn!("A portal in {} leads to {} which is not a zone", zone.name, portal.zone)
+            }
+        }
+    }
+}
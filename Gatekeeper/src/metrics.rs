@@ -0,0 +1,37 @@
+/**
+ * Counters for how the simulation tick is keeping up with its budget
+ */
+pub struct TickMetrics {
+    pub ticks: u64,
+    pub overruns: u64,
+    pub worst_overrun: f64
+}
+
+impl TickMetrics {
+    pub fn new() -> TickMetrics {
+        TickMetrics {
+            ticks: 0,
+            overruns: 0,
+            worst_overrun: 0.0
+        }
+    }
+
+    /**
+     * Record a tick that took `took` seconds against a budget of `budget` seconds
+     */
+    pub fn record(&mut self, took: f64, budget: f64) {
+        self.ticks += 1;
+
+        if took > budget {
+            let over = took - budget;
+            self.overruns += 1;
+
+            if over > self.worst_overrun {
+                self.worst_overrun = over;
+            }
+
+            warn!("Tick took {:.2}ms, {:.2}ms over budget ({} of {} ticks overran, worst {:.2}ms)",
+                took * 1000.0, over * 1000.0, self.overruns, self.ticks, self.worst_overrun * 1000.0);
+        }
+    }
+}
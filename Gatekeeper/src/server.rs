@@ -1,11 +1,27 @@
-use connection::Connection;
+use connection::{Connection, State};
+use user::{Accounts, AccountError, Authenticator, Job, Verified, Outcome, User, Role};
+use metrics::TickMetrics;
+use audit::AuditLog;
+use zone::Zone;
+use snapshot;
+use snapshot::{Snapshot, ZoneSnapshot};
 
 use std::io;
 use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
+use std::cmp;
+use std::mem;
+use std::path::PathBuf;
 
-use world_lib::{Map, World};
-use world_lib::message::{next, Message};
-use world_lib::entity::{Entity, EntityID, EntityType};
+use world_lib::World;
+use world_lib::map::{TileEdit, Portal};
+use world_lib::message::{encode, next, Message};
+use world_lib::message::protocol::{compatible, negotiate, PROTOCOL_VERSION, MIN_PROTOCOL_VERSION};
+use world_lib::entity::{Entity, EntityID, EntityType, Component, ComponentData, ComponentKind, WALK_SPEED};
+use world_lib::entity::{Position, Velocity, Body, Sprite, Health, Ai, Behaviour};
+use world_lib::math::Vec2;
+use world_lib::utils::seconds;
+use world_lib::collision;
 
 use mio::*;
 use mio::tcp::*;
@@ -15,12 +31,64 @@ pub struct Server {
     sock: TcpListener,
     token: Token,
     conns: Slab<Connection>,
-    world: World
+    zones: Vec<Zone>,
+    accounts: Accounts,
+    auth: Option<Authenticator>,
+    next_ticket: u64,
+    audit: AuditLog,
+    tick_ms: u64,
+    last_tick: Instant,
+    tick_metrics: TickMetrics,
+    snapshot: Option<PathBuf>,
+    snapshot_ms: u64,
+    shutdown_secs: u64,
+    closing: bool
+}
+
+pub enum Timer {
+    Tick,
+    Snapshot,
+
+    //Seconds left before shutting down
+    Countdown(u64),
+
+    //Checks left for send queues to empty before giving up on them
+    Drain(u32)
+}
+
+/**
+ * Sent to the server from other threads through the event loop's channel
+ */
+pub enum Notice {
+    Shutdown,
+
+    //A password check for the connection finished, with the ticket it was started with
+    Authenticated(Token, u64, Outcome)
 }
 
 impl Handler for Server {
-    type Timeout = ();
-    type Message = ();
+    type Timeout = Timer;
+    type Message = Notice;
+
+    fn timeout(&mut self, event_loop: &mut EventLoop<Server>, timer: Timer) {
+        match timer {
+            Timer::Tick => self.tick(event_loop),
+            Timer::Snapshot => self.periodic_snapshot(event_loop),
+            Timer::Countdown(secs) => self.countdown(secs, event_loop),
+            Timer::Drain(checks) => self.drain(checks, event_loop)
+        }
+    }
+
+    fn notify(&mut self, event_loop: &mut EventLoop<Server>, notice: Notice) {
+        match notice {
+            Notice::Shutdown => self.shut_down(event_loop),
+            Notice::Authenticated(token, ticket, outcome) => {
+                if self.authenticated(token, ticket, outcome, event_loop).is_err() {
+                    self.reset_connection(event_loop, token);
+                }
+            }
+        }
+    }
 
     fn ready(&mut self, event_loop: &mut EventLoop<Server>, token: Token, events: EventSet) {
         println!("events = {:?}", events);
@@ -71,12 +139,27 @@ impl Handler for Server {
 
 impl Server {
 
-    pub fn new(sock: TcpListener, world: World) -> Server {
+    /**
+     * Players start in the first zone. When asked to shut down players are
+     * warned then given shutdown_secs to finish up.
+     */
+    pub fn new(sock: TcpListener, zones: Vec<Zone>, accounts: Accounts, audit: AuditLog, tick_ms: u64, shutdown_secs: u64) -> Server {
         Server {
             sock: sock,
             token: Token(1),
             conns: Slab::new_starting_at(Token(2), 2048),
-            world: world
+            zones: zones,
+            accounts: accounts,
+            auth: None,
+            next_ticket: 0,
+            audit: audit,
+            tick_ms: tick_ms,
+            last_tick: Instant::now(),
+            tick_metrics: TickMetrics::new(),
+            snapshot: None,
+            snapshot_ms: 0,
+            shutdown_secs: shutdown_secs,
+            closing: false
         }
     }
 
@@ -105,6 +188,19 @@ impl Server {
         })
     }
 
+    /**
+     * Start the thread passwords are hashed and checked on, logins are refused until then
+     */
+    pub fn start_authenticator(&mut self, event_loop: &mut EventLoop<Server>) {
+        let notices = event_loop.channel();
+
+        self.auth = Some(Authenticator::start(move |token, ticket, outcome| {
+            if notices.send(Notice::Authenticated(token, ticket, outcome)).is_err() {
+                error!("Failed to hand a password check back to the server for {:?}", token);
+            }
+        }));
+    }
+
     fn grab_incoming(&mut self) -> Option<TcpStream> {
         match self.sock.accept() {
             Ok(incoming) => match incoming {
@@ -154,13 +250,20 @@ impl Server {
     }
 
     fn user_name(&mut self, token: Token) -> String {
-        self.find_connection_by_token(token).user.name.clone()
+        match self.find_connection_by_token(token).user {
+            Some(ref user) => user.name.clone(),
+            None => "Anon".to_string()
+        }
     }
 
-    fn entity_id(&mut self, token: Token) -> EntityID {
+    fn entity_id(&mut self, token: Token) -> Option<EntityID> {
         self.find_connection_by_token(token).entity
     }
 
+    fn zone_id(&mut self, token: Token) -> usize {
+        self.find_connection_by_token(token).zone
+    }
+
     fn read_from_connection(&mut self, event_loop: &mut EventLoop<Server>, token: Token) -> io::Result<()> {
         println!("server conn readable; token={:?}", token);
         self.is_message(event_loop, token)
@@ -201,6 +304,11 @@ impl Server {
 
 impl Server {
     fn send_buffer(&mut self, token: Token, buffer: &[u8], event_loop: &mut EventLoop<Server>) {
+        //The connection may have been reset by an earlier send
+        if !self.conns.contains(token) {
+            return;
+        }
+
         self.find_connection_by_token(token).send_message(buffer);
         if self.find_connection_by_token(token).reregister(event_loop).is_err() {
             self.reset_connection(event_loop, token)
@@ -230,11 +338,33 @@ impl Server {
 
 impl Server {
     fn send_message(&mut self, token: Token, message: &Message, event_loop: &mut EventLoop<Server>) {
-        self.send_buffer(token, (message.as_json() + "\0").as_bytes(), event_loop)
+        match encode(message) {
+            Ok(frame) => self.send_buffer(token, &frame, event_loop),
+            Err(e) => error!("Failed to encode message for {:?}, {:?}", token, e)
+        }
     }
 
     fn broadcast_message(&mut self, message: &Message, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
-        self.send_all_buffer(format!("{}\0", message.as_json()).as_bytes(), event_loop);
+        let frame = try!(encode(message));
+        self.send_all_buffer(&frame, event_loop);
+        Ok(())
+    }
+
+    /**
+     * Send a message to every player in a zone
+     */
+    fn broadcast_zone(&mut self, zone: usize, message: &Message, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        let frame = try!(encode(message));
+
+        let tokens: Vec<Token> = self.conns.iter()
+            .filter(|conn| conn.state == State::Playing && conn.zone == zone)
+            .map(|conn| conn.token)
+            .collect();
+
+        for token in tokens {
+            self.send_buffer(token, &frame, event_loop);
+        }
+
         Ok(())
     }
 }
@@ -251,6 +381,10 @@ impl Server {
     fn say_all(&mut self, msg: &str, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
         self.broadcast_message(&Message::Say(msg.to_string()), event_loop) 
     }
+
+    fn say_zone(&mut self, zone: usize, msg: &str, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        self.broadcast_zone(zone, &Message::Say(msg.to_string()), event_loop)
+    }
 }
 
 /**
@@ -262,10 +396,48 @@ impl Server {
     }
 
     fn handle_user_leaving(&mut self, token: Token, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        if self.find_connection_by_token(token).state != State::Playing {
+            return Ok(());
+        }
+
+        self.save_user(token);
+
         let name = self.user_name(token);
-        let eid = self.entity_id(token);
-        try!(self.say_all(&(name + " dissolved away"), event_loop));
-        self.remove_entity(eid, event_loop)
+        let zone = self.zone_id(token);
+        try!(self.say_zone(zone, &(name + " dissolved away"), event_loop));
+
+        match self.entity_id(token) {
+            Some(eid) => self.remove_entity(zone, eid, event_loop),
+            None => Ok(())
+        }
+    }
+
+    /**
+     * Copy the players entity and zone back into their character and persist it
+     */
+    fn save_user(&mut self, token: Token) {
+        let zone = self.zone_id(token);
+
+        let (pos, body) = match self.entity_id(token) {
+            Some(eid) => (self.zones[zone].world.get::<Position>(eid).cloned(), self.zones[zone].world.get::<Body>(eid).cloned()),
+            None => (None, None)
+        };
+
+        if let Some(ref mut user) = self.conns[token].user {
+            user.current_zone = zone;
+
+            if let Some(position) = pos {
+                user.character.pos = Some(position.pos);
+            }
+
+            if let Some(body) = body {
+                user.character.size = body.size;
+            }
+
+            if self.accounts.save(user).is_err() {
+                error!("Could not save user {}", user.name);
+            }
+        }
     }
 }
 
@@ -275,98 +447,893 @@ impl Server {
 
 impl Server {
 
+    /**
+     * Check the client speaks a protocol we understand before anything else
+     */
+    fn hello(&mut self, token: Token, message: Message, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        match message {
+            Message::Hello(version, capabilities) => {
+                if !compatible(version) {
+                    info!("Refusing {:?} with protocol version {}", token, version);
+                    let reason = format!("Unsupported protocol version {}, server supports {} to {}", version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION);
+                    return self.kill(token, &reason, event_loop);
+                }
+
+                let capabilities = negotiate(capabilities);
+                self.find_connection_by_token(token).capabilities = capabilities;
+                self.find_connection_by_token(token).state = State::AwaitingLogin;
+                self.send_message(token, &Message::Welcome(PROTOCOL_VERSION, capabilities), event_loop);
+                Ok(())
+            },
+            _ => { self.kill(token, "Expected Hello before any other message", event_loop) }
+        }
+    }
+
     /**
      * Do the user handshake
      */
     fn handshake(&mut self, token: Token, message: Message, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
         match message {
-            Message::Login(username, _) => {
-                self.find_connection_by_token(token).user.set_name(&username);
-                try!(self.update_world_personal(token, event_loop));
-                try!(self.say_all(&format!("{} has joined the server", username), event_loop));
-                let player_ent = Server::default_entity();
-                self.find_connection_by_token(token).entity = player_ent.id;
-                self.update_or_insert(&player_ent, event_loop)
+            Message::Login(..) | Message::Register(..) if self.closing => {
+                self.kill(token, SHUTDOWN_REASON, event_loop)
+            },
+            Message::Login(username, password) => {
+                if self.is_logged_in(&username) {
+                    return self.kill(token, "User is already logged in", event_loop);
+                }
+
+                match self.accounts.find(&username) {
+                    Ok(user) => self.authenticate(token, Job::Login(user, password), event_loop),
+                    Err(e) => {
+                        info!("Failed login for {}, {:?}", username, e);
+                        self.kill(token, e.reason(), event_loop)
+                    }
+                }
+            },
+            Message::Register(username, password) => {
+                match self.accounts.check_new(&username, &password) {
+                    Ok(()) => self.authenticate(token, Job::Register(username, password), event_loop),
+                    Err(e) => self.kill(token, e.reason(), event_loop)
+                }
+            },
+            _ => { self.kill(token, "Expected Login or Register", event_loop) }
+        }
+    }
+
+    /**
+     * Send the password off to be hashed or checked, the connection waits
+     * until Notice::Authenticated comes back
+     */
+    fn authenticate(&mut self, token: Token, job: Job, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+
+        let queued = match self.auth {
+            Some(ref auth) => auth.submit(token, ticket, job),
+            None => false
+        };
+
+        if !queued {
+            return self.kill(token, AccountError::Busy.reason(), event_loop);
+        }
+
+        self.find_connection_by_token(token).state = State::Authenticating(ticket);
+        Ok(())
+    }
+
+    /**
+     * Finish logging in once the password has been checked. The connection
+     * may have closed meanwhile and its token been reused, the ticket tells
+     * whether it is still the one waiting.
+     */
+    fn authenticated(&mut self, token: Token, ticket: u64, outcome: Outcome, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        if !self.conns.contains(token) || self.find_connection_by_token(token).state != State::Authenticating(ticket) {
+            debug!("Dropped a password check for {:?} which is no longer waiting", token);
+            return Ok(());
+        }
+
+        if self.closing {
+            return self.kill(token, SHUTDOWN_REASON, event_loop);
+        }
+
+        let user = match outcome {
+            Ok(Verified::LoggedIn(user)) => user,
+            Ok(Verified::Hashed(name, hash)) => match self.accounts.create(&name, &hash) {
+                Ok(user) => {
+                    info!("Created account {}", name);
+                    user
+                },
+                Err(e) => return self.kill(token, e.reason(), event_loop)
             },
-            _ => { self.kill(token, "Bad login", event_loop) }
+            Err(e) => {
+                info!("Failed login for {:?}, {:?}", token, e);
+                return self.kill(token, e.reason(), event_loop);
+            }
+        };
+
+        //Another connection may have logged in as them while the password was checked
+        if self.is_logged_in(&user.name) {
+            return self.kill(token, "User is already logged in", event_loop);
+        }
+
+        self.logged_in(token, user, event_loop)
+    }
+
+    fn is_logged_in(&self, username: &str) -> bool {
+        self.conns.iter().any(|conn| match conn.user {
+            Some(ref user) => user.name == username,
+            None => false
+        })
+    }
+
+    /**
+     * Called once the user has been authenticated
+     */
+    fn logged_in(&mut self, token: Token, user: User, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        let username = user.name.clone();
+        let (mut zone, mut pos, size) = (user.current_zone, user.character.pos, user.character.size);
+
+        //The zone they were saved in may no longer be hosted, start them over
+        if zone >= self.zones.len() {
+            warn!("{} was in zone {} which no longer exists", username, zone);
+            zone = 0;
+            pos = None;
         }
+
+        //Positions are the top left of the body, spawns are where its feet go
+        let pos = pos.unwrap_or_else(|| self.spawn_point(zone) - Vec2::new(size.x / 2.0, size.y));
+
+        self.find_connection_by_token(token).user = Some(user);
+        self.find_connection_by_token(token).state = State::Playing;
+        self.find_connection_by_token(token).zone = zone;
+        try!(self.update_world_personal(token, event_loop));
+        try!(self.say_zone(zone, &format!("{} has joined the server", username), event_loop));
+        let eid = self.spawn(zone, vec![
+            ComponentData::Type(EntityType::Character),
+            ComponentData::Position(Position { pos: pos }),
+            ComponentData::Body(Body { size: size }),
+            ComponentData::Sprite(Sprite { name: "male".to_string() }),
+            ComponentData::Health(Health::new(PLAYER_HEALTH))
+        ]);
+        self.find_connection_by_token(token).entity = Some(eid);
+        self.send_message(token, &Message::Control(eid), event_loop);
+
+        //So the client only offers what the server will allow
+        let role = self.role(token);
+        self.send_message(token, &Message::Role(role), event_loop);
+        Ok(())
+    }
+
+    /**
+     * Where new characters stand in a zone, the first walkable tile if its map has no player spawn
+     */
+    fn spawn_point(&self, zone: usize) -> Vec2 {
+        let map = &self.zones[zone].world.map;
+
+        map.player_spawn().unwrap_or_else(|| {
+            let (x, y) = (0..map.height)
+                .flat_map(|y| (0..map.width).map(move |x| (x, y)))
+                .find(|&(x, y)| map.walkable(x, y))
+                .unwrap_or((0, 0));
+
+            map.tile_to_world(x, y)
+        })
     }
 
     fn client_message(&mut self, token: Token, message: Message, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
         match message {
             Message::Say(msg) => {
-                let msg = self.find_connection_by_token(token).user.name.to_string() + ": " + &msg;
-                self.say_all(&msg, event_loop)
+                let msg = self.user_name(token) + ": " + &msg;
+                let zone = self.zone_id(token);
+                self.say_zone(zone, &msg, event_loop)
+            },
+            Message::Move(delta) => {
+                self.move_player(token, delta, event_loop)
             },
-            Message::Map(mapdata) => {
-                self.world.map = Map::from_json(&mapdata);
-                self.update_world(event_loop)
+            Message::MoveTo(x, y) => {
+                self.walk_player(token, (x, y), event_loop)
+            },
+            Message::SetTile(edit) => {
+                self.edit_tiles(token, vec![edit], event_loop)
+            },
+            Message::SetTiles(edits) => {
+                self.edit_tiles(token, edits, event_loop)
+            },
+            Message::Map(mut map) => {
+                if let Err(e) = map.validate() {
+                    let reply = format!("Rejected map, {:?}", e);
+                    self.send_message(token, &Message::Error(reply), event_loop);
+                    return Ok(());
+                }
+
+                //Which tiles can be walked on is the server's to decide, not the builder's
+                let zone = self.zone_id(token);
+                map.registry = self.zones[zone].world.map.registry.clone();
+                self.zones[zone].world.map = map;
+                self.update_world(zone, event_loop)
             },
             _ => Err(Error::new(ErrorKind::Other, "Unhandled Message"))
         }
     }
 
+    /**
+     * Apply tile edits and send only the edits on to everyone else in the zone
+     */
+    fn edit_tiles(&mut self, token: Token, edits: Vec<TileEdit>, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        let zone = self.zone_id(token);
+
+        if let Err(e) = self.zones[zone].world.map.apply_all(&edits) {
+            let reply = format!("Rejected tile edit, {:?}", e);
+            self.send_message(token, &Message::Error(reply), event_loop);
+            return Ok(());
+        }
+
+        let message = if edits.len() == 1 {
+            Message::SetTile(edits[0])
+        } else {
+            Message::SetTiles(edits)
+        };
+
+        self.broadcast_zone(zone, &message, event_loop)
+    }
+
     fn kill(&mut self, token: Token, message: &str, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
         self.send_message(token, &Message::Kill(message.to_string()), event_loop);
         Err(Error::new(ErrorKind::Other, "Killed Connection"))
     }
 
     /**
-     * Handling incoming message doing login if not handshaken else passing through to client_message
+     * Handling incoming message, enforcing Hello then Login before passing through to client_message
      */
     fn handle_message(&mut self, token: Token, message: Message, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
-        if !self.find_connection_by_token(token).handshake_done {
-            self.find_connection_by_token(token).handshake_done = true;
-            self.handshake(token, message, event_loop)
-        } else {
-            self.client_message(token, message, event_loop)
+        match self.find_connection_by_token(token).state {
+            State::AwaitingHello => self.hello(token, message, event_loop),
+            State::AwaitingLogin => self.handshake(token, message, event_loop),
+            State::Authenticating(_) => self.kill(token, "Expected nothing until logged in", event_loop),
+            State::Playing => {
+                if self.authorize(token, &message) {
+                    self.client_message(token, message, event_loop)
+                } else {
+                    let reply = format!("You do not have permission to {}", message.name());
+                    self.send_message(token, &Message::Error(reply), event_loop);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    fn role(&mut self, token: Token) -> Role {
+        match self.find_connection_by_token(token).user {
+            Some(ref user) => user.role(),
+            None => Role::Player
         }
     }
 
+    /**
+     * Check the user may send this message, privileged and refused messages are audited
+     */
+    fn authorize(&mut self, token: Token, message: &Message) -> bool {
+        let role = self.role(token);
+        let allowed = role.can(message);
+
+        if !allowed || Role::required(message) != Some(Role::Player) {
+            let name = self.user_name(token);
+            self.audit.record(&format!("{}({:?})", name, role), message.name(), allowed);
+        }
+
+        allowed
+    }
+
     fn is_message(&mut self, event_loop: &mut EventLoop<Server>, token: Token) -> io::Result<()> {
-        let message = try!(self.find_connection_by_token(token).readable());
+        let data = try!(self.find_connection_by_token(token).readable());
+        self.find_connection_by_token(token).buffer.extend_from_slice(&data);
 
-        let mut local_buffer_copy = self.find_connection_by_token(token).buffer.iter().cloned().chain(message).collect();
-        while let Some((msg, remain)) = try!(next(&local_buffer_copy)) {
+        while let Some(msg) = try!(next(&mut self.find_connection_by_token(token).buffer)) {
             try!(self.handle_message(token, msg, event_loop));
-            local_buffer_copy = remain;
         }
-        
-        self.find_connection_by_token(token).buffer = local_buffer_copy;
+
+        Ok(())
+    }
+}
+
+/**
+ * Simulation tick
+ */
+
+/**
+ * The most ticks worth of time a single late tick will simulate, so a stall
+ * doesn't move entities far enough in one step to skip past walls
+ */
+const MAX_TICK_LAG: u32 = 4;
+
+impl Server {
+    pub fn start_ticking(&mut self, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        let delay = self.tick_ms;
+        self.last_tick = Instant::now();
+        self.schedule_tick(event_loop, delay)
+    }
+
+    fn schedule_tick(&mut self, event_loop: &mut EventLoop<Server>, delay: u64) -> io::Result<()> {
+        event_loop.timeout_ms(Timer::Tick, delay)
+            .map(|_| ())
+            .map_err(|e| Error::new(ErrorKind::Other, format!("Failed to schedule tick, {:?}", e)))
+    }
+
+    /**
+     * Step every zone forward by the time since the last tick, move players
+     * through any portals they reached and send out everything that changed.
+     * Timers fire late by up to the event loop's timer tick, so the time
+     * passed is measured rather than assumed to be tick_ms.
+     */
+    fn tick(&mut self, event_loop: &mut EventLoop<Server>) {
+        let start = Instant::now();
+        let elapsed = cmp::min(start.duration_since(self.last_tick), Duration::from_millis(self.tick_ms) * MAX_TICK_LAG);
+        self.last_tick = start;
+
+        for zone in self.zones.iter_mut() {
+            let changed = zone.world.update(elapsed);
+            zone.dirty.extend(changed);
+        }
+
+        self.use_portals(event_loop);
+
+        for zone in 0..self.zones.len() {
+            if let Err(e) = self.flush_entities(zone, event_loop) {
+                error!("Failed to flush entity updates for {}, {:?}", self.zones[zone].name, e);
+            }
+        }
+
+        let took = seconds(start.elapsed());
+        self.tick_metrics.record(took, self.tick_ms as f64 / 1000.0);
+
+        //Subtract the time this tick took from the next delay to hold a fixed rate
+        let delay = self.tick_ms.saturating_sub((took * 1000.0) as u64);
+
+        if let Err(e) = self.schedule_tick(event_loop, cmp::max(delay, 1)) {
+            error!("{:?}, shutting down", e);
+            event_loop.shutdown();
+        }
+    }
+}
+
+/**
+ * World snapshots
+ */
+
+impl Server {
+
+    /**
+     * Save every zone to path now and then every every_ms
+     */
+    pub fn start_snapshots(&mut self, event_loop: &mut EventLoop<Server>, path: PathBuf, every_ms: u64) -> io::Result<()> {
+        self.snapshot = Some(path);
+        self.snapshot_ms = every_ms;
+        self.schedule_snapshot(event_loop)
+    }
+
+    fn schedule_snapshot(&mut self, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        event_loop.timeout_ms(Timer::Snapshot, self.snapshot_ms)
+            .map(|_| ())
+            .map_err(|e| Error::new(ErrorKind::Other, format!("Failed to schedule snapshot, {:?}", e)))
+    }
+
+    fn periodic_snapshot(&mut self, event_loop: &mut EventLoop<Server>) {
+        if let Err(e) = self.save_snapshot() {
+            error!("Failed to save a snapshot, {:?}", e);
+        }
+
+        if let Err(e) = self.schedule_snapshot(event_loop) {
+            error!("{:?}, no more snapshots will be taken", e);
+        }
+    }
+
+    /**
+     * Write every zone's world out, leaving out players' entities as they are
+     * saved with their accounts and spawned again when they log in
+     */
+    pub fn save_snapshot(&self) -> io::Result<()> {
+        let path = match self.snapshot {
+            Some(ref path) => path,
+            None => return Ok(())
+        };
+
+        let mut zones = Vec::new();
+
+        for (i, zone) in self.zones.iter().enumerate() {
+            let mut world = zone.world.clone();
+
+            for eid in self.conns.iter().filter(|conn| conn.zone == i).filter_map(|conn| conn.entity) {
+                world.remove(eid);
+            }
+
+            zones.push(try!(ZoneSnapshot::new(&zone.name, &world)));
+        }
+
+        let start = Instant::now();
+        try!(snapshot::save(path, &Snapshot::new(zones)));
+        debug!("Saved a snapshot in {:.3}s", seconds(start.elapsed()));
+        Ok(())
+    }
+}
+
+/**
+ * Shutting down
+ */
+
+const SHUTDOWN_REASON: &'static str = "server shutting down";
+
+/**
+ * How often, and how many times, to check every client's queue has been sent before giving up on them
+ */
+const DRAIN_MS: u64 = 50;
+const DRAIN_CHECKS: u32 = 40;
+
+impl Server {
+
+    /**
+     * Stop accepting connections, warn everyone and close once the countdown runs out
+     */
+    fn shut_down(&mut self, event_loop: &mut EventLoop<Server>) {
+        if self.closing {
+            return;
+        }
+
+        info!("Shutting down in {} seconds", self.shutdown_secs);
+        self.closing = true;
+
+        if let Err(e) = event_loop.deregister(&self.sock) {
+            error!("Failed to stop listening for connections, {:?}", e);
+        }
+
+        let secs = self.shutdown_secs;
+        self.countdown(secs, event_loop);
+    }
+
+    fn countdown(&mut self, secs: u64, event_loop: &mut EventLoop<Server>) {
+        if secs == 0 {
+            return self.close(event_loop);
+        }
+
+        if secs == self.shutdown_secs || secs <= 5 {
+            if let Err(e) = self.say_all(&format!("Server shutting down in {} seconds", secs), event_loop) {
+                error!("Failed to announce shutdown, {:?}", e);
+            }
+        }
+
+        if let Err(e) = event_loop.timeout_ms(Timer::Countdown(secs - 1), 1000) {
+            error!("Failed to schedule shutdown countdown, {:?}", e);
+            self.close(event_loop);
+        }
+    }
+
+    /**
+     * Tell every client why they are being dropped then wait for it to be sent
+     */
+    fn close(&mut self, event_loop: &mut EventLoop<Server>) {
+        let tokens: Vec<Token> = self.conns.iter().map(|conn| conn.token).collect();
+
+        for token in tokens {
+            self.send_message(token, &Message::Kill(SHUTDOWN_REASON.to_string()), event_loop);
+        }
+
+        self.drain(DRAIN_CHECKS, event_loop);
+    }
+
+    /**
+     * Stop the event loop once every send queue is empty, or the checks run out
+     */
+    fn drain(&mut self, checks: u32, event_loop: &mut EventLoop<Server>) {
+        if self.conns.iter().all(|conn| conn.is_drained()) {
+            return event_loop.shutdown();
+        }
+
+        if checks == 0 || event_loop.timeout_ms(Timer::Drain(checks - 1), DRAIN_MS).is_err() {
+            warn!("Shutting down before every client was sent everything");
+            event_loop.shutdown();
+        }
+    }
+
+    /**
+     * Save every player still connected and the world once the event loop has
+     * stopped, however it stopped, then close their connections
+     */
+    pub fn finish(&mut self) {
+        let tokens: Vec<Token> = self.conns.iter().map(|conn| conn.token).collect();
+
+        for token in tokens {
+            if self.find_connection_by_token(token).state == State::Playing {
+                self.save_user(token);
+            }
+
+            if self.find_connection_by_token(token).write_remaining().is_err() {
+                println!("could not write remaining to client before shutting down");
+            }
+
+            if self.find_connection_by_token(token).shutdown().is_err() {
+                println!("could not shutdown TcpStream before shutting down");
+            }
+        }
+
+        if let Err(e) = self.save_snapshot() {
+            error!("Failed to save a snapshot on shutdown, {:?}", e);
+        }
+    }
+}
+
+/**
+ * Player movement
+ */
+
+const PLAYER_HEALTH: i32 = 100;
+
+/**
+ * Headroom over WALK_SPEED to absorb network jitter
+ */
+const SPEED_TOLERANCE: f64 = 1.25;
+
+/**
+ * The most time worth of movement a player can bank, stops players saving up
+ * by standing still and then teleporting
+ */
+const MAX_MOVE_WINDOW: f64 = 0.25;
+
+/**
+ * Finding a route is the most expensive thing a player can ask for, clicks
+ * closer together than this are ignored
+ */
+const MIN_ROUTE_MS: u64 = 250;
+
+impl Server {
+
+    /**
+     * Validate a requested move against the speed limit then apply as much of it
+     * as collision allows. Rejected
+     * moves are answered with the players current entity so the client can snap back.
+     *
+     * Each player has a budget of distance topped up as time passes and spent
+     * by each move, so several moves arriving together in one read are judged
+     * on the time they cover between them rather than the time between frames.
+     */
+    fn move_player(&mut self, token: Token, delta: Vec2, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        let eid = match self.entity_id(token) {
+            Some(eid) => eid,
+            None => return Ok(())
+        };
+
+        let zone = self.zone_id(token);
+
+        let pos = match self.zones[zone].world.get::<Position>(eid) {
+            Some(position) => position.pos,
+            None => return Ok(())
+        };
+
+        let allowed = {
+            let conn = self.find_connection_by_token(token);
+            let now = Instant::now();
+            let elapsed = seconds(now.duration_since(conn.last_move));
+
+            conn.last_move = now;
+            conn.move_budget = (conn.move_budget + WALK_SPEED * SPEED_TOLERANCE * elapsed).min(WALK_SPEED * SPEED_TOLERANCE * MAX_MOVE_WINDOW);
+            conn.move_budget
+        };
+
+        let distance = delta.length();
+
+        if !distance.is_finite() || distance > allowed {
+            debug!("Rejected move of {:?} by {:?}", delta, token);
+            let correction = Message::Components(vec![(eid, ComponentData::Position(Position { pos: pos }))]);
+            self.send_message(token, &correction, event_loop);
+            return Ok(());
+        }
+
+        self.find_connection_by_token(token).move_budget -= distance;
+        self.stop_walking(zone, eid);
+
+        //Walls and other players stop the move or slide it along them
+        let target = collision::slide(&self.zones[zone].world, eid, pos, delta);
+
+        if target != pos {
+            self.zones[zone].set(eid, Position { pos: target });
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Route the player to a tile, the world then walks them there each tick
+     */
+    fn walk_player(&mut self, token: Token, goal: (usize, usize), event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        let eid = match self.entity_id(token) {
+            Some(eid) => eid,
+            None => return Ok(())
+        };
+
+        let now = Instant::now();
+        let recent = match self.find_connection_by_token(token).last_route {
+            Some(last) => now.duration_since(last) < Duration::from_millis(MIN_ROUTE_MS),
+            None => false
+        };
+
+        if recent {
+            debug!("Ignoring a route request from {:?} that came too soon after the last", token);
+            return Ok(());
+        }
+
+        self.find_connection_by_token(token).last_route = Some(now);
+
+        let zone = self.zone_id(token);
+
+        if self.zones[zone].world.route(eid, goal) {
+            self.zones[zone].dirty.insert((eid, Ai::kind()));
+        } else {
+            self.send_message(token, &Message::Error("There is no way to get there".to_string()), event_loop);
+        }
+
         Ok(())
     }
+
+    /**
+     * Moving by hand cancels any walk in progress
+     */
+    fn stop_walking(&mut self, zone: usize, eid: EntityID) {
+        let walking = match self.zones[zone].world.get::<Ai>(eid) {
+            Some(ai) => ai.behaviour != Behaviour::Idle,
+            None => false
+        };
+
+        if walking {
+            self.zones[zone].set(eid, Ai { behaviour: Behaviour::Idle });
+            self.zones[zone].set(eid, Velocity { vel: Vec2::zero() });
+        }
+    }
+}
+
+/**
+ * Travel between zones
+ */
+
+impl Server {
+
+    /**
+     * Send every player standing in a portal on to its zone
+     */
+    fn use_portals(&mut self, event_loop: &mut EventLoop<Server>) {
+        let travellers: Vec<(Token, Portal)> = self.conns.iter()
+            .filter(|conn| conn.state == State::Playing)
+            .filter_map(|conn| conn.entity
+                .and_then(|eid| self.zones[conn.zone].world.portal(eid).cloned())
+                .map(|portal| (conn.token, portal)))
+            .collect();
+
+        for (token, portal) in travellers {
+            if let Err(e) = self.transfer(token, &portal, event_loop) {
+                error!("Failed to move {:?} to {}, {:?}", token, portal.zone, e);
+            }
+        }
+    }
+
+    /**
+     * Move a players entity into the portals zone with its feet on the target.
+     * The client is sent the new zone's world and the id of its new entity.
+     */
+    fn transfer(&mut self, token: Token, portal: &Portal, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        let to = match self.zones.iter().position(|zone| zone.name == portal.zone) {
+            Some(to) => to,
+            None => {
+                warn!("Portal leads to {} which is not a zone", portal.zone);
+                return Ok(());
+            }
+        };
+
+        let eid = match self.entity_id(token) {
+            Some(eid) => eid,
+            None => return Ok(())
+        };
+
+        let from = self.zone_id(token);
+        let size = self.zones[from].world.get::<Body>(eid).map(|body| body.size).unwrap_or(Vec2::zero());
+
+        //Any walk in progress was planned on the old map
+        let mut components: Vec<ComponentData> = self.zones[from].world.components.collect(eid).into_iter()
+            .filter(|data| match *data {
+                ComponentData::Position(_) | ComponentData::Velocity(_) | ComponentData::Ai(_) => false,
+                _ => true
+            })
+            .collect();
+
+        components.push(ComponentData::Position(Position { pos: portal.target - Vec2::new(size.x / 2.0, size.y) }));
+
+        let name = self.user_name(token);
+        try!(self.remove_entity(from, eid, event_loop));
+        try!(self.say_zone(from, &format!("{} left for {}", name, portal.zone), event_loop));
+
+        let eid = self.spawn(to, components);
+        self.find_connection_by_token(token).zone = to;
+        self.find_connection_by_token(token).entity = Some(eid);
+
+        try!(self.update_world_personal(token, event_loop));
+        self.send_message(token, &Message::Control(eid), event_loop);
+        self.say_zone(to, &format!("{} arrived from {}", name, self.zones[from].name), event_loop)
+    }
 }
 
 /**
  * Entity creation and update logic
  */
+
 impl Server {
-    fn default_entity() -> Entity {
-        Entity::new(EntityType::Character, (30.0, 30.0), (32.0, 32.0))
+
+    /**
+     * New entities reach players on the next flush once they come into view
+     */
+    fn spawn(&mut self, zone: usize, components: Vec<ComponentData>) -> EntityID {
+        self.zones[zone].world.spawn(components)
     }
 
-    fn world_message(&self) -> Message {
-        Message::World(self.world.as_json())
+    /**
+     * The zone's world without its entities, clients are sent those as they come into view
+     */
+    fn world_message(&self, zone: usize) -> Message {
+        Message::World(World::new(self.zones[zone].world.map.clone()))
     }
 
-    pub fn update_world(&mut self, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
-        let msg = self.world_message();
-        self.broadcast_message(&msg, event_loop)
+    /**
+     * Send everyone in the zone its world again, entities are resent on the next flush
+     */
+    pub fn update_world(&mut self, zone: usize, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        for conn in self.conns.iter_mut().filter(|conn| conn.zone == zone) {
+            conn.visible.clear();
+        }
+
+        let msg = self.world_message(zone);
+        self.broadcast_zone(zone, &msg, event_loop)
     }
 
     pub fn update_world_personal(&mut self, token: Token, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
-        let msg = self.world_message();
+        self.find_connection_by_token(token).visible.clear();
+        let zone = self.zone_id(token);
+        let msg = self.world_message(zone);
         self.send_message(token, &msg, event_loop);
         Ok(())
     }
 
-    pub fn update_or_insert(&mut self, entity: &Entity, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
-        self.world.update_or_insert(entity);
-        self.broadcast_message(&Message::Entity(entity.as_json()), event_loop)
+    /**
+     * Send each player in the zone the entities that came into view whole, removals
+     * for those that left it and the changed components of everything still in view
+     */
+    fn flush_entities(&mut self, zone: usize, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        let mut updates = Vec::new();
+        let mut removed = Vec::new();
+
+        let dirty: Vec<(EntityID, ComponentKind)> = self.zones[zone].dirty.drain().collect();
+
+        for (eid, kind) in dirty {
+            if !self.zones[zone].world.contains(eid) {
+                continue;
+            }
+
+            match self.zones[zone].world.components.data(eid, kind) {
+                Some(data) => updates.push((eid, data)),
+                None => removed.push((eid, kind))
+            }
+        }
+
+        let players: Vec<(Token, EntityID)> = self.conns.iter()
+            .filter(|conn| conn.state == State::Playing && conn.zone == zone)
+            .filter_map(|conn| conn.entity.map(|eid| (conn.token, eid)))
+            .collect();
+
+        for (token, eid) in players {
+            if !self.conns.contains(token) {
+                continue;
+            }
+
+            let view = self.zones[zone].view(eid);
+            let before = mem::replace(&mut self.find_connection_by_token(token).visible, view.clone());
+
+            let entered: Vec<Entity> = view.difference(&before).filter_map(|&id| self.zones[zone].world.entity(id)).collect();
+
+            let changed: Vec<(EntityID, ComponentData)> = updates.iter()
+                .filter(|&&(id, _)| before.contains(&id) && view.contains(&id))
+                .cloned()
+                .collect();
+
+            if !entered.is_empty() {
+                self.send_message(token, &Message::Entities(entered), event_loop);
+            }
+
+            if !changed.is_empty() {
+                self.send_message(token, &Message::Components(changed), event_loop);
+            }
+
+            for &(id, kind) in removed.iter().filter(|&&(id, _)| before.contains(&id) && view.contains(&id)) {
+                self.send_message(token, &Message::RemoveComponent(id, kind), event_loop);
+            }
+
+            for &id in before.difference(&view) {
+                self.send_message(token, &Message::RemoveEntity(id), event_loop);
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Remove an entity from a zone and despawn it for everyone there who could see it
+     */
+    pub fn remove_entity(&mut self, zone: usize, entity: EntityID, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
+        self.zones[zone].world.remove(entity);
+
+        let watching: Vec<Token> = self.conns.iter_mut()
+            .filter(|conn| conn.zone == zone && conn.visible.contains(&entity))
+            .map(|conn| conn.token)
+            .collect();
+
+        for token in watching {
+            self.find_connection_by_token(token).visible.remove(&entity);
+            self.send_message(token, &Message::RemoveEntity(entity), event_loop);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use user::MemoryStorage;
+    use world_lib::Map;
+    use world_lib::map::{Tile, TileDef, Spawn};
+
+    fn server_with(shutdown_secs: u64) -> Server {
+        let sock = TcpListener::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        let zones = vec![Zone::new("town", Map::new(4, 4))];
+
+        Server::new(sock, zones, Accounts::new(Box::new(MemoryStorage::new())), AuditLog::new(), 50, shutdown_secs)
     }
 
-    pub fn remove_entity(&mut self, entity: EntityID, event_loop: &mut EventLoop<Server>) -> io::Result<()> {
-        self.world.remove(entity);
-        self.broadcast_message(&Message::RemoveEntity(entity), event_loop)
+    #[test]
+    fn players_spawn_on_the_first_walkable_tile_without_a_spawn() {
+        const WALL: Tile = Tile { x: 1, y: 0 };
+        let mut server = server_with(0);
+
+        {
+            let map = &mut server.zones[0].world.map;
+            map.registry.tiles.push(TileDef { x: WALL.x, y: WALL.y, name: "wall".to_string(), walkable: false, cost: 1.0, blocks_sight: true, damage: 0 });
+            map.layers[0] = vec![Some(WALL); 16];
+            map.layers[0][6] = Some(Tile { x: 0, y: 0 });
+        }
+
+        assert_eq!(server.spawn_point(0), server.zones[0].world.map.tile_to_world(2, 1));
+
+        let spawn = Spawn { name: "start".to_string(), kind: "player".to_string(), pos: Vec2::new(5.0, 7.0) };
+        server.zones[0].world.map.spawns.push(spawn);
+        assert_eq!(server.spawn_point(0), Vec2::new(5.0, 7.0));
+    }
+
+    #[test]
+    fn a_zero_countdown_closes_at_once() {
+        let mut event_loop = EventLoop::new().unwrap();
+        let mut server = server_with(0);
+
+        server.countdown(0, &mut event_loop);
+        assert!(!event_loop.is_running());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn shutting_down_waits_for_the_countdown() {
+        let mut event_loop = EventLoop::new().unwrap();
+        let mut server = server_with(10);
+
+        server.shut_down(&mut event_loop);
+        assert!(server.closing);
+        assert!(event_loop.is_running());
+
+        let mut event_loop = EventLoop::new().unwrap();
+        let mut server = server_with(0);
+
+        server.shut_down(&mut event_loop);
+        assert!(server.closing);
+        assert!(!event_loop.is_running());
+    }
+}
@@ -0,0 +1,188 @@
+use std::io;
+use std::io::Read;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+use rustc_serialize::json;
+use rustc_serialize::json::Json;
+
+use world_lib::World;
+use world_lib::schema;
+use world_lib::schema::Kind;
+use world_lib::utils::{to_io, write_atomic};
+use zone::Zone;
+
+/**
+ * Bumped whenever the snapshot layout changes, snapshots outside
+ * MIN_SNAPSHOT_VERSION to SNAPSHOT_VERSION are refused
+ */
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/**
+ * Version 1 held bare worlds, which schema can still work out the version of
+ */
+pub const MIN_SNAPSHOT_VERSION: u32 = 1;
+
+/**
+ * A zone's world is kept as a schema document so it can be migrated when World changes
+ */
+#[derive(RustcEncodable)]
+pub struct ZoneSnapshot {
+    pub name: String,
+    pub world: Json
+}
+
+impl ZoneSnapshot {
+    pub fn new(name: &str, world: &World) -> io::Result<ZoneSnapshot> {
+        Ok(ZoneSnapshot {
+            name: name.to_string(),
+            world: try!(to_io(Json::from_str(&world.as_json())))
+        })
+    }
+}
+
+/**
+ * Every zone's world, in the order the server hosts them so players' saved zones still line up
+ */
+#[derive(RustcEncodable)]
+pub struct Snapshot {
+    pub version: u32,
+    pub zones: Vec<ZoneSnapshot>
+}
+
+impl Snapshot {
+    pub fn new(zones: Vec<ZoneSnapshot>) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            zones: zones
+        }
+    }
+
+    pub fn into_zones(self) -> io::Result<Vec<Zone>> {
+        let mut zones = Vec::new();
+
+        for ZoneSnapshot { name, world } in self.zones {
+            let world: World = try!(schema::decode_json(Kind::World, world).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("Zone {}, {:?}", name, e))
+            }));
+
+            zones.push(Zone::from_world(&name, world));
+        }
+
+        Ok(zones)
+    }
+}
+
+/**
+ * Read a snapshot, None if there isn't one yet
+ */
+pub fn load(path: &Path) -> io::Result<Option<Snapshot>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e)
+    };
+
+    let mut data = String::new();
+    try!(file.read_to_string(&mut data));
+
+    let document = try!(to_io(Json::from_str(&data)));
+    let version = document.find("version").and_then(|version| version.as_u64());
+
+    match version {
+        Some(version) if version >= MIN_SNAPSHOT_VERSION as u64 && version <= SNAPSHOT_VERSION as u64 => {},
+        _ => {
+            let reason = format!("Snapshot version {:?}, expected {} to {}", version, MIN_SNAPSHOT_VERSION, SNAPSHOT_VERSION);
+            return Err(invalid(&reason));
+        }
+    }
+
+    //Worlds stay as JSON until schema has upgraded them
+    let mut zones = Vec::new();
+
+    for zone in try!(document.find("zones").and_then(|zones| zones.as_array()).ok_or(invalid("Snapshot has no zones"))) {
+        zones.push(ZoneSnapshot {
+            name: try!(zone.find("name").and_then(|name| name.as_string()).ok_or(invalid("Zone has no name"))).to_string(),
+            world: try!(zone.find("world").cloned().ok_or(invalid("Zone has no world")))
+        });
+    }
+
+    Ok(Some(Snapshot {
+        version: version.unwrap_or(0) as u32,
+        zones: zones
+    }))
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+/**
+ * Replace the last snapshot, a crash part way through leaves the last one as it was
+ */
+pub fn save(path: &Path, snapshot: &Snapshot) -> io::Result<()> {
+    let data = try!(to_io(json::encode(snapshot)));
+
+    if let Some(dir) = path.parent() {
+        try!(fs::create_dir_all(dir));
+    }
+
+    write_atomic(path, data.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use world_lib::{World, Map};
+    use world_lib::entity::{ComponentData, Position, Health};
+    use world_lib::math::Vec2;
+
+    fn path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("gatekeeper-snapshot-{}.json", name))
+    }
+
+    fn world() -> World {
+        let mut world = World::new(Map::new(6, 4));
+        world.spawn(vec![ComponentData::Position(Position { pos: Vec2::new(10.0, 20.0) }), ComponentData::Health(Health::new(5))]);
+        world
+    }
+
+    #[test]
+    fn round_trips_every_zone() {
+        let path = path("round-trip");
+        let zones = vec![ZoneSnapshot::new("town", &world()).unwrap(), ZoneSnapshot::new("cave", &World::new(Map::new(2, 2))).unwrap()];
+
+        save(&path, &Snapshot::new(zones)).unwrap();
+        let snapshot = load(&path).unwrap().unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+
+        let zones = snapshot.into_zones().unwrap();
+        assert_eq!(zones.iter().map(|zone| zone.name.as_ref()).collect::<Vec<&str>>(), vec!["town", "cave"]);
+        assert_eq!(zones[0].world.as_json(), world().as_json());
+        assert_eq!(zones[1].world.as_json(), World::new(Map::new(2, 2)).as_json());
+    }
+
+    #[test]
+    fn missing_snapshots_are_none() {
+        assert!(load(&path("missing")).unwrap().is_none());
+    }
+
+    #[test]
+    fn refuses_newer_snapshots() {
+        let path = path("newer");
+        let data = format!("{{\"version\":{},\"zones\":[]}}", SNAPSHOT_VERSION + 1);
+        File::create(&path).unwrap().write_all(data.as_bytes()).unwrap();
+
+        let loaded = load(&path);
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(loaded.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+    }
+}
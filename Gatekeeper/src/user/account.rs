@@ -0,0 +1,166 @@
+use user::User;
+use user::storage::Storage;
+
+const MAX_NAME_LENGTH: usize = 24;
+const MIN_PASSWORD_LENGTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccountError {
+	InvalidName,
+	InvalidPassword,
+	NameTaken,
+	UnknownUser,
+	BadPassword,
+
+	//Too many logins are waiting on their passwords to be checked
+	Busy,
+
+	Internal
+}
+
+impl AccountError {
+
+	/**
+	 * The reason sent to the client in a Message::Kill. Unknown users and wrong
+	 * passwords share one so a login can't tell which names exist.
+	 */
+	pub fn reason(&self) -> &'static str {
+		match *self {
+			AccountError::InvalidName => "Invalid username",
+			AccountError::InvalidPassword => "Password is too short",
+			AccountError::NameTaken => "Username is already taken",
+			AccountError::UnknownUser | AccountError::BadPassword => "Bad username or password",
+			AccountError::Busy => "The server is busy, try again shortly",
+			AccountError::Internal => "Internal server error"
+		}
+	}
+}
+
+pub struct Accounts {
+	storage: Box<Storage>
+}
+
+impl Accounts {
+
+	pub fn new(storage: Box<Storage>) -> Accounts {
+		Accounts {
+			storage: storage
+		}
+	}
+
+	fn load(&self, name: &str) -> Result<Option<User>, AccountError> {
+		self.storage.load(name).map_err(|e| {
+			error!("Failed to load account {}, {:?}", name, e);
+			AccountError::Internal
+		})
+	}
+
+	fn valid_name(name: &str) -> bool {
+		name.len() != 0 && name.len() <= MAX_NAME_LENGTH && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+	}
+
+	/**
+	 * Check a new account could be made before its password is hashed
+	 */
+	pub fn check_new(&self, name: &str, password: &str) -> Result<(), AccountError> {
+
+		if !Accounts::valid_name(name) {
+			return Err(AccountError::InvalidName);
+		}
+
+		if password.len() < MIN_PASSWORD_LENGTH {
+			return Err(AccountError::InvalidPassword);
+		}
+
+		if try!(self.load(name)).is_some() {
+			return Err(AccountError::NameTaken);
+		}
+
+		Ok(())
+	}
+
+	/**
+	 * Create a new account from a salted scrypt hash of its password. The name
+	 * is checked again as it may have been taken while the password was hashed.
+	 */
+	pub fn create(&mut self, name: &str, hash: &str) -> Result<User, AccountError> {
+
+		if try!(self.load(name)).is_some() {
+			return Err(AccountError::NameTaken);
+		}
+
+		let user = User::new(name, hash);
+		try!(self.save(&user));
+		Ok(user)
+	}
+
+	/**
+	 * The saved user logging in, whose password is then checked against the stored
+	 * hash. None if there is no such user, a name no account could have included.
+	 */
+	pub fn find(&self, name: &str) -> Result<Option<User>, AccountError> {
+
+		if !Accounts::valid_name(name) {
+			return Ok(None);
+		}
+
+		self.load(name)
+	}
+
+	pub fn save(&mut self, user: &User) -> Result<(), AccountError> {
+		self.storage.save(user).map_err(|e| {
+			error!("Failed to save account {}, {:?}", user.name, e);
+			AccountError::Internal
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use user::MemoryStorage;
+
+	fn accounts() -> Accounts {
+		Accounts::new(Box::new(MemoryStorage::new()))
+	}
+
+	#[test]
+	fn creates_accounts_that_can_be_found() {
+		let mut accounts = accounts();
+
+		assert_eq!(accounts.check_new("alice", "hunter2"), Ok(()));
+		let user = accounts.create("alice", "hash").unwrap();
+		assert_eq!((user.name.as_ref(), user.password.as_ref()), ("alice", "hash"));
+
+		let found = accounts.find("alice").unwrap().unwrap();
+		assert_eq!((found.name.as_ref(), found.password.as_ref()), ("alice", "hash"));
+		assert!(accounts.find("bob").unwrap().is_none());
+	}
+
+	#[test]
+	fn refuses_names_already_taken() {
+		let mut accounts = accounts();
+		accounts.create("alice", "hash").unwrap();
+
+		assert_eq!(accounts.check_new("alice", "hunter2"), Err(AccountError::NameTaken));
+		assert_eq!(accounts.create("alice", "other").err(), Some(AccountError::NameTaken));
+		assert_eq!(accounts.find("alice").unwrap().unwrap().password, "hash");
+	}
+
+	#[test]
+	fn refuses_bad_names_and_short_passwords() {
+		let accounts = accounts();
+
+		for name in ["", "../alice", "a b", "abcdefghijklmnopqrstuvwxyz"].iter() {
+			assert_eq!(accounts.check_new(name, "hunter2"), Err(AccountError::InvalidName));
+			assert!(accounts.find(name).unwrap().is_none());
+		}
+
+		assert_eq!(accounts.check_new("alice", "abc"), Err(AccountError::InvalidPassword));
+	}
+
+	#[test]
+	fn unknown_users_and_wrong_passwords_look_the_same() {
+		assert_eq!(AccountError::UnknownUser.reason(), AccountError::BadPassword.reason());
+	}
+}
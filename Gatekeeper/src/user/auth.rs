@@ -0,0 +1,152 @@
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::thread;
+use crypto::scrypt::{scrypt_simple, scrypt_check, ScryptParams};
+use mio::Token;
+use user::User;
+use user::account::AccountError;
+
+/**
+ * scrypt work factor (2^14 rounds, r = 8, p = 1). Each hash takes tens of
+ * milliseconds and 16MiB which makes offline guessing expensive, far too
+ * slow to run on the event loop.
+ */
+const SCRYPT_LOG_N: u8 = 14;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+/**
+ * How many jobs can wait for the worker, more are turned away until it catches up
+ */
+const MAX_QUEUED: usize = 16;
+
+pub enum Job {
+	//Check a password against an existing user's stored hash. None for an unknown
+	//name, which is checked against a dummy hash so it takes just as long.
+	Login(Option<User>, String),
+
+	//Hash the password for a new account with the given name
+	Register(String, String)
+}
+
+pub enum Verified {
+	LoggedIn(User),
+
+	//The name and password hash for a new account
+	Hashed(String, String)
+}
+
+pub type Outcome = Result<Verified, AccountError>;
+
+/**
+ * Hashes and checks passwords one at a time on its own thread. A flood of
+ * logins only delays other logins rather than every player's tick.
+ */
+pub struct Authenticator {
+	jobs: SyncSender<(Token, u64, Job)>
+}
+
+impl Authenticator {
+
+	/**
+	 * done is called on the worker thread with the token and ticket each job was submitted with
+	 */
+	pub fn start<F>(done: F) -> Authenticator where F: Fn(Token, u64, Outcome) + Send + 'static {
+		let (jobs, queue) = sync_channel::<(Token, u64, Job)>(MAX_QUEUED);
+
+		thread::spawn(move || {
+			let dummy = match scrypt_simple("", &params()) {
+				Ok(hash) => hash,
+				Err(e) => {
+					error!("Failed to hash the dummy password, {:?}", e);
+					return;
+				}
+			};
+
+			for (token, ticket, job) in queue.iter() {
+				done(token, ticket, run(job, &dummy));
+			}
+		});
+
+		Authenticator {
+			jobs: jobs
+		}
+	}
+
+	/**
+	 * Queue a job, false if too many are already waiting
+	 */
+	pub fn submit(&self, token: Token, ticket: u64, job: Job) -> bool {
+		self.jobs.try_send((token, ticket, job)).is_ok()
+	}
+}
+
+fn params() -> ScryptParams {
+	ScryptParams::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)
+}
+
+/**
+ * dummy is a hash of the same cost as a real one, checked when the user is unknown
+ */
+fn run(job: Job, dummy: &str) -> Outcome {
+	match job {
+		Job::Login(None, password) => {
+			let _ = scrypt_check(&password, dummy);
+			Err(AccountError::UnknownUser)
+		},
+		Job::Login(Some(user), password) => match scrypt_check(&password, &user.password) {
+			Ok(true) => Ok(Verified::LoggedIn(user)),
+			Ok(false) => Err(AccountError::BadPassword),
+			Err(e) => {
+				error!("Stored hash for {} is invalid, {}", user.name, e);
+				Err(AccountError::Internal)
+			}
+		},
+		Job::Register(name, password) => {
+			match scrypt_simple(&password, &params()) {
+				Ok(hash) => Ok(Verified::Hashed(name, hash)),
+				Err(e) => {
+					error!("Failed to hash password for {}, {:?}", name, e);
+					Err(AccountError::Internal)
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crypto::scrypt::{scrypt_simple, ScryptParams};
+	use user::User;
+	use user::account::AccountError;
+
+	//Far cheaper than the real work factor, checks read it from the hash
+	fn hash(password: &str) -> String {
+		scrypt_simple(password, &ScryptParams::new(4, 8, 1)).unwrap()
+	}
+
+	#[test]
+	fn logs_in_with_the_right_password() {
+		let user = User::new("alice", &hash("hunter2"));
+
+		match run(Job::Login(Some(user), "hunter2".to_string()), &hash("")) {
+			Ok(Verified::LoggedIn(user)) => assert_eq!(user.name, "alice"),
+			_ => panic!("expected alice to log in")
+		}
+	}
+
+	#[test]
+	fn refuses_wrong_passwords_and_unknown_users() {
+		let user = User::new("alice", &hash("hunter2"));
+
+		assert_eq!(run(Job::Login(Some(user), "hunter3".to_string()), &hash("")).err(), Some(AccountError::BadPassword));
+		assert_eq!(run(Job::Login(None, "hunter2".to_string()), &hash("")).err(), Some(AccountError::UnknownUser));
+	}
+
+	#[test]
+	fn refuses_corrupt_stored_hashes() {
+		let user = User::new("alice", "not a hash");
+
+		assert_eq!(run(Job::Login(Some(user), "hunter2".to_string()), &hash("")).err(), Some(AccountError::Internal));
+	}
+}
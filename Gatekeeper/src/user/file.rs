@@ -0,0 +1,48 @@
+use std::io;
+use std::io::Read;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use rustc_serialize::json;
+use world_lib::utils::{to_io, write_atomic};
+use user::User;
+use user::storage::Storage;
+
+/**
+ * Stores each account as a JSON file named after the user
+ */
+pub struct FileStorage {
+	root: PathBuf
+}
+
+impl FileStorage {
+	pub fn new(root: &Path) -> io::Result<FileStorage> {
+		try!(fs::create_dir_all(root));
+		Ok(FileStorage {
+			root: root.to_path_buf()
+		})
+	}
+
+	fn path(&self, name: &str) -> PathBuf {
+		self.root.join(format!("{}.json", name))
+	}
+}
+
+impl Storage for FileStorage {
+	fn load(&self, name: &str) -> io::Result<Option<User>> {
+		let mut file = match File::open(self.path(name)) {
+			Ok(file) => file,
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(e)
+		};
+
+		let mut data = String::new();
+		try!(file.read_to_string(&mut data));
+		Ok(Some(try!(to_io(json::decode(&data)))))
+	}
+
+	fn save(&mut self, user: &User) -> io::Result<()> {
+		let data = try!(to_io(json::encode(user)));
+		write_atomic(&self.path(&user.name), data.as_bytes())
+	}
+}
@@ -0,0 +1,30 @@
+use std::io;
+use std::collections::HashMap;
+use user::User;
+use user::storage::Storage;
+
+/**
+ * Keeps users in memory, everything is lost when the server stops
+ */
+pub struct MemoryStorage {
+	users: HashMap<String, User>
+}
+
+impl MemoryStorage {
+	pub fn new() -> MemoryStorage {
+		MemoryStorage {
+			users: HashMap::new()
+		}
+	}
+}
+
+impl Storage for MemoryStorage {
+	fn load(&self, name: &str) -> io::Result<Option<User>> {
+		Ok(self.users.get(name).cloned())
+	}
+
+	fn save(&mut self, user: &User) -> io::Result<()> {
+		self.users.insert(user.name.clone(), user.clone());
+		Ok(())
+	}
+}
@@ -1,3 +1,14 @@
 mod user;
+mod account;
+mod auth;
+mod storage;
+mod file;
+mod memory;
 
-pub use user::user::User;
\ No newline at end of file
+pub use user::user::{User, Character};
+pub use user::account::{Accounts, AccountError};
+pub use user::auth::{Authenticator, Job, Verified, Outcome};
+pub use user::storage::Storage;
+pub use user::file::FileStorage;
+pub use user::memory::MemoryStorage;
+pub use world_lib::message::Role;
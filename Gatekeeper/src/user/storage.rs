@@ -0,0 +1,10 @@
+use std::io;
+use user::User;
+
+/**
+ * A backend that accounts and their characters are persisted to
+ */
+pub trait Storage {
+	fn load(&self, name: &str) -> io::Result<Option<User>>;
+	fn save(&mut self, user: &User) -> io::Result<()>;
+}
@@ -1,17 +1,48 @@
+use world_lib::math::Vec2;
+use world_lib::message::Role;
+
+/**
+ * The persisted state of a players character
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct Character {
+	//None until they have been in the world, they start at their zone's player spawn
+	pub pos: Option<Vec2>,
+	pub size: Vec2
+}
+
+impl Character {
+	pub fn new() -> Character {
+		Character {
+			pos: None,
+			size: Vec2::new(32.0, 32.0)
+		}
+	}
+}
+
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
 pub struct User {
 	pub name: String,
-	pub current_zone: usize
+	pub password: String,
+	pub current_zone: usize,
+	pub character: Character,
+
+	//None for accounts saved before roles existed, treated as a Player
+	pub role: Option<Role>
 }
 
 impl User {
-	pub fn load(user_name: &str, zone: usize) -> User {
+	pub fn new(user_name: &str, password_hash: &str) -> User {
 		User {
 			name: user_name.to_string(),
-			current_zone: zone
+			password: password_hash.to_string(),
+			current_zone: 0,
+			character: Character::new(),
+			role: Some(Role::Player)
 		}
 	}
 
-	pub fn set_name(&mut self, user_name: &str) {
-		self.name = user_name.to_string();
+	pub fn role(&self) -> Role {
+		self.role.unwrap_or(Role::Player)
 	}
-}
\ No newline at end of file
+}
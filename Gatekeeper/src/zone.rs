@@ -0,0 +1,61 @@
+use std::collections::HashSet;
+
+use world_lib::{World, Map};
+use world_lib::entity::{EntityID, Component, ComponentKind};
+use world_lib::fov::{field_of_view, VIEW_RADIUS};
+
+/**
+ * A named part of the game with its own map and entities, players only
+ * see and hear those in the same zone
+ */
+pub struct Zone {
+    pub name: String,
+    pub world: World,
+    pub dirty: HashSet<(EntityID, ComponentKind)>
+}
+
+impl Zone {
+    pub fn new(name: &str, map: Map) -> Zone {
+        Zone::from_world(name, World::new(map))
+    }
+
+    pub fn from_world(name: &str, world: World) -> Zone {
+        Zone {
+            name: name.to_string(),
+            world: world,
+            dirty: HashSet::new()
+        }
+    }
+
+    /**
+     * Changes are batched and sent to clients at the end of the next tick
+     */
+    pub fn set<T: Component>(&mut self, eid: EntityID, component: T) {
+        self.world.insert(eid, component);
+        self.dirty.insert((eid, T::kind()));
+    }
+
+    /**
+     * Everything the entity can see, always including itself
+     */
+    pub fn view(&self, eid: EntityID) -> HashSet<EntityID> {
+        let mut seen = HashSet::new();
+        seen.insert(eid);
+
+        let (tile, feet) = match (self.world.tile(eid), self.world.feet(eid)) {
+            (Some(tile), Some(feet)) => (tile, feet),
+            _ => return seen
+        };
+
+        let fov = field_of_view(&self.world.map, tile, VIEW_RADIUS);
+        let radius = VIEW_RADIUS as f64 * self.world.map.tile_width;
+
+        for other in self.world.near(feet, radius) {
+            if self.world.tile(other).map(|tile| fov.contains(&tile)).unwrap_or(false) {
+                seen.insert(other);
+            }
+        }
+
+        seen
+    }
+}
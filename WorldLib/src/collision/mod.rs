@@ -0,0 +1,186 @@
+use entity::{EntityID, Body};
+use map::Map;
+use math::{Vec2, Rect};
+use world::World;
+
+/**
+ * Does a box stand on anything it can't? An entity stands on the bottom
+ * edge of its box, so its corners and middle must all be on walkable tiles
+ * and the whole box must be on the map.
+ */
+pub fn hits_tiles(map: &Map, bounds: &Rect) -> bool {
+	if !map.in_bounds(bounds) {
+		return true;
+	}
+
+	let (left, right) = (bounds.pos.x, bounds.max().x);
+	let bottom = bounds.max().y;
+	let footprint = [Vec2::new(left, bottom), bounds.bottom_centre(), Vec2::new(right, bottom)];
+
+	footprint.iter().any(|&point| match map.world_to_tile(point) {
+		Some((x, y)) => !map.walkable(x, y),
+		None => true
+	})
+}
+
+/**
+ * Other entities with a Body the box would overlap at to but doesn't at from.
+ * Entities already overlapping, such as players spawned on the same spot,
+ * can always move apart.
+ */
+pub fn hits_entities(world: &World, id: EntityID, from: &Rect, to: &Rect) -> Vec<EntityID> {
+	world.in_rect(to)
+		.into_iter()
+		.filter(|&other| other != id && world.get::<Body>(other).is_some())
+		.filter(|&other| match world.bounds(other) {
+			Some(bounds) => to.intersects(&bounds) && !from.intersects(&bounds),
+			None => false
+		})
+		.collect()
+}
+
+/**
+ * Could the entity move from from to to without hitting anything?
+ */
+pub fn can_move(world: &World, id: EntityID, from: Vec2, to: Vec2) -> bool {
+	let size = match world.bounds(id) {
+		Some(bounds) => bounds.size,
+		None => return false
+	};
+
+	let (before, after) = (Rect::new(from, size), Rect::new(to, size));
+
+	if world.get::<Body>(id).is_none() {
+		return world.map.in_bounds(&after);
+	}
+
+	!hits_tiles(&world.map, &after) && hits_entities(world, id, &before, &after).is_empty()
+}
+
+/**
+ * Move the entity by delta as far as collision allows. If the full move is
+ * blocked it slides along whichever axis is still free, otherwise it stays put.
+ */
+pub fn slide(world: &World, id: EntityID, from: Vec2, delta: Vec2) -> Vec2 {
+	for &step in [delta, Vec2::new(delta.x, 0.0), Vec2::new(0.0, delta.y)].iter() {
+		if step.is_zero() {
+			continue;
+		}
+
+		let to = from + step;
+
+		if can_move(world, id, from, to) {
+			return to;
+		}
+	}
+
+	from
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use entity::{EntityID, ComponentData, Position, Body};
+	use map::{Map, Tile, TileDef, TileRegistry};
+	use math::{Vec2, Rect};
+	use world::World;
+
+	const WALL: Tile = Tile { x: 1, y: 0 };
+	const SIZE: Vec2 = Vec2 { x: 32.0, y: 48.0 };
+
+	fn world() -> World {
+		let mut map = Map::new(16, 32);
+		map.registry = TileRegistry {
+			tiles: vec![TileDef {
+				x: WALL.x,
+				y: WALL.y,
+				name: "wall".to_string(),
+				walkable: false,
+				cost: 1.0,
+				blocks_sight: true,
+				damage: 0
+			}]
+		};
+		World::new(map)
+	}
+
+	fn body(world: &mut World, pos: Vec2) -> EntityID {
+		world.spawn(vec![
+			ComponentData::Position(Position { pos: pos }),
+			ComponentData::Body(Body { size: SIZE })
+		])
+	}
+
+	#[test]
+	fn slides_along_the_edge_of_the_map() {
+		let mut world = world();
+		let from = Vec2::new(100.0, 5.0);
+		let id = body(&mut world, from);
+
+		//Up and right would leave the map, right alone doesn't
+		assert_eq!(slide(&world, id, from, Vec2::new(10.0, -20.0)), Vec2::new(110.0, 5.0));
+		assert_eq!(slide(&world, id, from, Vec2::new(0.0, -20.0)), from);
+		assert_eq!(slide(&world, id, from, Vec2::new(-200.0, 0.0)), from);
+	}
+
+	/**
+	 * Wall off every tile the box's footprint stands on
+	 */
+	fn wall_under(world: &mut World, pos: Vec2) {
+		let bounds = Rect::new(pos, SIZE);
+		let bottom = bounds.max().y;
+
+		for &point in [Vec2::new(bounds.pos.x, bottom), bounds.bottom_centre(), Vec2::new(bounds.max().x, bottom)].iter() {
+			let (x, y) = world.map.world_to_tile(point).unwrap();
+			let idx = world.map.idx(x, y);
+			world.map.layers[0][idx] = Some(WALL);
+		}
+	}
+
+	#[test]
+	fn stops_before_walls() {
+		let mut world = world();
+		let from = Vec2::new(200.0, 100.0);
+		let id = body(&mut world, from);
+		let right = Vec2::new(64.0, 0.0);
+		let up = Vec2::new(0.0, -4.0);
+
+		assert_eq!(slide(&world, id, from, right), from + right);
+
+		wall_under(&mut world, from + right);
+		wall_under(&mut world, from + right + up);
+
+		assert!(hits_tiles(&world.map, &Rect::new(from + right, SIZE)));
+		assert!(!hits_tiles(&world.map, &Rect::new(from + up, SIZE)));
+		assert_eq!(slide(&world, id, from, right), from);
+
+		//Sliding keeps the free axis of a move that runs into the wall
+		assert_eq!(slide(&world, id, from, right + up), from + up);
+	}
+
+	#[test]
+	fn other_bodies_block_movement() {
+		let mut world = world();
+		let from = Vec2::new(200.0, 100.0);
+		let id = body(&mut world, from);
+		let other = body(&mut world, Vec2::new(250.0, 100.0));
+
+		assert_eq!(slide(&world, id, from, Vec2::new(30.0, 0.0)), from);
+		assert_eq!(hits_entities(&world, id, &Rect::new(from, SIZE), &Rect::new(from + Vec2::new(30.0, 0.0), SIZE)), vec![other]);
+		assert_eq!(slide(&world, id, from, Vec2::new(10.0, 0.0)), from + Vec2::new(10.0, 0.0));
+
+		//Entities without a body don't get in the way
+		world.remove_component::<Body>(other);
+		assert_eq!(slide(&world, id, from, Vec2::new(30.0, 0.0)), from + Vec2::new(30.0, 0.0));
+	}
+
+	#[test]
+	fn overlapping_bodies_can_move_apart() {
+		let mut world = world();
+		let from = Vec2::new(200.0, 100.0);
+		let id = body(&mut world, from);
+		body(&mut world, from + Vec2::new(10.0, 0.0));
+
+		assert_eq!(slide(&world, id, from, Vec2::new(-10.0, 0.0)), from + Vec2::new(-10.0, 0.0));
+	}
+}
@@ -0,0 +1,88 @@
+use math::Vec2;
+use entity::EntityID;
+
+#[derive(RustcEncodable, RustcDecodable, Clone, Copy, Debug, PartialEq)]
+pub enum EntityType {
+	Scene,
+	Character
+}
+
+/**
+ * Top left corner of the entity in world units
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+	pub pos: Vec2
+}
+
+/**
+ * World units per second
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Copy, Debug, PartialEq)]
+pub struct Velocity {
+	pub vel: Vec2
+}
+
+/**
+ * The physical extent of the entity from its position
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Copy, Debug, PartialEq)]
+pub struct Body {
+	pub size: Vec2
+}
+
+/**
+ * Name of the image in the clients assets the entity is drawn with
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct Sprite {
+	pub name: String
+}
+
+#[derive(RustcEncodable, RustcDecodable, Clone, Copy, Debug, PartialEq)]
+pub struct Health {
+	pub current: i32,
+	pub max: i32
+}
+
+impl Health {
+	pub fn new(max: i32) -> Health {
+		Health {
+			current: max,
+			max: max
+		}
+	}
+
+	pub fn is_dead(&self) -> bool {
+		self.current <= 0
+	}
+}
+
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct Item {
+	pub name: String,
+	pub count: u32
+}
+
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct Inventory {
+	pub items: Vec<Item>
+}
+
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub enum Behaviour {
+	Idle,
+
+	//Walk between the points in order, the index is the point currently headed for
+	Patrol(Vec<Vec2>, usize),
+
+	Follow(EntityID),
+
+	//Walk through the points in order then stop
+	Walk(Vec<Vec2>)
+}
+
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct Ai {
+	pub behaviour: Behaviour
+}
@@ -1,48 +1,162 @@
-use std::time::Duration;
-use rustc_serialize::json;
-use math::Vec2d;
+use rustc_serialize::{Encodable, Decodable};
+use std::fmt::Debug;
+use schema;
+use schema::{Kind, SchemaError};
 
-pub type EntityID = usize;
+mod store;
+mod components;
 
-pub fn null_id() -> EntityID {
-	0
-}
+pub use self::store::{Store, Iter};
+pub use self::components::*;
 
-#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
-pub enum EntityType {
-	Scene,
-	Character
+/**
+ * Identifies an entity within a World. The generation changes each time an
+ * index is reused so stale IDs never refer to a newer entity.
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EntityID {
+	pub index: u32,
+	pub generation: u32
 }
 
-#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
-pub struct Entity {
-	pub id: usize,
-	pub t: EntityType,
-	pub pos: Vec2d,
-	pub size: Vec2d
+/**
+ * How fast a character walks in world units per second
+ */
+pub const WALK_SPEED: f64 = 96.0;
+
+/**
+ * A piece of data that can be attached to an entity, stored in its own Store
+ * within Components and replicated on its own
+ */
+pub trait Component: Encodable + Decodable + Clone + Debug {
+	fn kind() -> ComponentKind;
+	fn store(components: &Components) -> &Store<Self>;
+	fn store_mut(components: &mut Components) -> &mut Store<Self>;
+	fn wrap(self) -> ComponentData;
 }
 
-impl Entity {
-	pub fn new(etype: EntityType, position: Vec2d, size: Vec2d) -> Entity {
-		unsafe {
-			static mut lid: usize = 0;
-			lid = lid + 1;
-			Entity {
-				id: lid,
-				t: etype,
-				pos: position,
-				size: size
+/**
+ * Registers every component type. Each entry generates a Store field on
+ * Components, a ComponentKind and a ComponentData variant and the Component
+ * impl tying them together, so adding a component is a one line change.
+ */
+macro_rules! components {
+	($($variant:ident($t:ident) => $field:ident),*) => {
+
+		#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+		pub struct Components {
+			$(pub $field: Store<$t>),*
+		}
+
+		#[derive(RustcEncodable, RustcDecodable, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+		pub enum ComponentKind {
+			$($variant),*
+		}
+
+		#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+		pub enum ComponentData {
+			$($variant($t)),*
+		}
+
+		impl ComponentData {
+			pub fn kind(&self) -> ComponentKind {
+				match *self {
+					$(ComponentData::$variant(..) => ComponentKind::$variant),*
+				}
 			}
 		}
+
+		impl Components {
+			pub fn new() -> Components {
+				Components {
+					$($field: Store::new()),*
+				}
+			}
+
+			/**
+			 * Every component attached to id
+			 */
+			pub fn collect(&self, id: EntityID) -> Vec<ComponentData> {
+				let mut found = Vec::new();
+				$(
+					if let Some(component) = self.$field.get(id) {
+						found.push(ComponentData::$variant(component.clone()));
+					}
+				)*
+				found
+			}
+
+			pub fn data(&self, id: EntityID, kind: ComponentKind) -> Option<ComponentData> {
+				match kind {
+					$(ComponentKind::$variant => self.$field.get(id).map(|c| ComponentData::$variant(c.clone()))),*
+				}
+			}
+
+			pub fn set(&mut self, id: EntityID, data: ComponentData) {
+				match data {
+					$(ComponentData::$variant(component) => { self.$field.insert(id, component); }),*
+				}
+			}
+
+			pub fn remove(&mut self, id: EntityID, kind: ComponentKind) {
+				match kind {
+					$(ComponentKind::$variant => { self.$field.remove(id); }),*
+				}
+			}
+
+			pub fn remove_all(&mut self, id: EntityID) {
+				$(self.$field.remove(id);)*
+			}
+		}
+
+		$(
+			impl Component for $t {
+				fn kind() -> ComponentKind {
+					ComponentKind::$variant
+				}
+
+				fn store(components: &Components) -> &Store<$t> {
+					&components.$field
+				}
+
+				fn store_mut(components: &mut Components) -> &mut Store<$t> {
+					&mut components.$field
+				}
+
+				fn wrap(self) -> ComponentData {
+					ComponentData::$variant(self)
+				}
+			}
+		)*
 	}
+}
+
+components! {
+	Type(EntityType) => types,
+	Position(Position) => positions,
+	Velocity(Velocity) => velocities,
+	Body(Body) => bodies,
+	Sprite(Sprite) => sprites,
+	Health(Health) => healths,
+	Inventory(Inventory) => inventories,
+	Ai(Ai) => ais
+}
+
+/**
+ * An entity and all of its components, used to spawn and replicate whole entities
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct Entity {
+	pub id: EntityID,
+	pub components: Vec<ComponentData>
+}
 
-	pub fn from_json(t: &str) -> Entity {
-		json::decode(t).unwrap()
+impl Entity {
+	pub fn from_json(t: &str) -> Result<Entity, SchemaError> {
+		schema::decode(Kind::Entity, t)
 	}
 
 	pub fn as_json(&self) -> String {
-		json::encode(self).unwrap()
+		schema::encode(Kind::Entity, self).unwrap()
 	}
-
-	pub fn update(&mut self, time: Duration) {}
-}
\ No newline at end of file
+}
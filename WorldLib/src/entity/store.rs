@@ -0,0 +1,95 @@
+use std::slice;
+use entity::EntityID;
+
+/**
+ * Sparse storage for one component type, indexed by EntityID::index. Each slot
+ * keeps the full ID so a stale ID never reads a newer entity's component.
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct Store<T> {
+	slots: Vec<Option<(EntityID, T)>>
+}
+
+impl<T> Store<T> {
+
+	pub fn new() -> Store<T> {
+		Store {
+			slots: Vec::new()
+		}
+	}
+
+	pub fn get(&self, id: EntityID) -> Option<&T> {
+		match self.slots.get(id.index as usize) {
+			Some(&Some((ref owner, ref value))) if *owner == id => Some(value),
+			_ => None
+		}
+	}
+
+	pub fn get_mut(&mut self, id: EntityID) -> Option<&mut T> {
+		match self.slots.get_mut(id.index as usize) {
+			Some(&mut Some((ref owner, ref mut value))) if *owner == id => Some(value),
+			_ => None
+		}
+	}
+
+	pub fn contains(&self, id: EntityID) -> bool {
+		self.get(id).is_some()
+	}
+
+	/**
+	 * Set the component for id, returning the one it replaced
+	 */
+	pub fn insert(&mut self, id: EntityID, value: T) -> Option<T> {
+		let index = id.index as usize;
+
+		while self.slots.len() <= index {
+			self.slots.push(None);
+		}
+
+		match self.slots[index].take() {
+			Some((owner, previous)) => {
+				self.slots[index] = Some((id, value));
+				if owner == id { Some(previous) } else { None }
+			},
+			None => {
+				self.slots[index] = Some((id, value));
+				None
+			}
+		}
+	}
+
+	pub fn remove(&mut self, id: EntityID) -> Option<T> {
+		if !self.contains(id) {
+			return None;
+		}
+
+		self.slots[id.index as usize].take().map(|(_, value)| value)
+	}
+
+	pub fn iter(&self) -> Iter<T> {
+		Iter {
+			inner: self.slots.iter()
+		}
+	}
+
+	pub fn ids(&self) -> Vec<EntityID> {
+		self.iter().map(|(id, _)| id).collect()
+	}
+}
+
+pub struct Iter<'a, T: 'a> {
+	inner: slice::Iter<'a, Option<(EntityID, T)>>
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+	type Item = (EntityID, &'a T);
+
+	fn next(&mut self) -> Option<(EntityID, &'a T)> {
+		while let Some(slot) = self.inner.next() {
+			if let Some((id, ref value)) = *slot {
+				return Some((id, value));
+			}
+		}
+		None
+	}
+}
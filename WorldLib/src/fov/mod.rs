@@ -0,0 +1,276 @@
+use std::collections::HashSet;
+use map::Map;
+use path::TilePos;
+
+/**
+ * How many tiles a character can see in any direction
+ */
+pub const VIEW_RADIUS: isize = 10;
+
+/**
+ * A slope as an exact fraction so the scan is symmetric, den is always positive
+ */
+#[derive(Debug, Clone, Copy)]
+struct Slope {
+	num: isize,
+	den: isize
+}
+
+fn floor_div(a: isize, b: isize) -> isize {
+	let q = a / b;
+	if (a % b != 0) && ((a < 0) != (b < 0)) { q - 1 } else { q }
+}
+
+fn ceil_div(a: isize, b: isize) -> isize {
+	-floor_div(-a, b)
+}
+
+/**
+ * One row of a quadrant being scanned, depth tiles out from the origin
+ */
+#[derive(Debug, Clone, Copy)]
+struct Row {
+	depth: isize,
+	start: Slope,
+	end: Slope
+}
+
+impl Row {
+
+	/**
+	 * Columns whose centre lies between the start and end slopes, ties rounding inwards
+	 */
+	fn columns(&self) -> (isize, isize) {
+		let min = floor_div(2 * self.depth * self.start.num + self.start.den, 2 * self.start.den);
+		let max = ceil_div(2 * self.depth * self.end.num - self.end.den, 2 * self.end.den);
+		(min, max)
+	}
+
+	/**
+	 * Floors are only revealed if the line to their centre is inside the row,
+	 * which is what makes seeing symmetric
+	 */
+	fn is_symmetric(&self, col: isize) -> bool {
+		col * self.start.den >= self.depth * self.start.num && col * self.end.den <= self.depth * self.end.num
+	}
+
+	fn next(&self) -> Row {
+		Row {
+			depth: self.depth + 1,
+			start: self.start,
+			end: self.end
+		}
+	}
+}
+
+fn slope(depth: isize, col: isize) -> Slope {
+	Slope {
+		num: 2 * col - 1,
+		den: 2 * depth
+	}
+}
+
+/**
+ * Turn a (depth, column) within one of the four quadrants into a diamond coordinate
+ */
+fn transform(quadrant: usize, (u, v): (isize, isize), depth: isize, col: isize) -> (isize, isize) {
+	match quadrant {
+		0 => (u + col, v - depth),
+		1 => (u + col, v + depth),
+		2 => (u + depth, v + col),
+		_ => (u - depth, v + col)
+	}
+}
+
+/**
+ * Every tile visible from origin within radius tiles using symmetric
+ * shadowcasting. The scan runs on the diamond grid where tiles sharing an
+ * edge are neighbours, so the staggered rows need no special cases. Tiles
+ * that block sight are visible but hide what is behind them and anything
+ * beyond the edge of the map is treated as blocking.
+ */
+pub fn field_of_view(map: &Map, origin: TilePos, radius: isize) -> HashSet<TilePos> {
+	let mut visible = HashSet::new();
+
+	if !map.contains(origin.0, origin.1) {
+		return visible;
+	}
+
+	visible.insert(origin);
+
+	let centre = Map::to_diamond(origin);
+
+	let blocks = |pos: (isize, isize)| match map.from_diamond(pos) {
+		Some((x, y)) => map.blocks_sight(x, y),
+		None => true
+	};
+
+	for quadrant in 0..4 {
+		let mut rows = vec![Row {
+			depth: 1,
+			start: Slope { num: -1, den: 1 },
+			end: Slope { num: 1, den: 1 }
+		}];
+
+		while let Some(mut row) = rows.pop() {
+			if row.depth > radius {
+				continue;
+			}
+
+			let (min, max) = row.columns();
+			let mut previous: Option<bool> = None;
+
+			for col in min..(max + 1) {
+				let pos = transform(quadrant, centre, row.depth, col);
+				let wall = blocks(pos);
+
+				if (wall || row.is_symmetric(col)) && row.depth * row.depth + col * col <= radius * radius {
+					if let Some(tile) = map.from_diamond(pos) {
+						visible.insert(tile);
+					}
+				}
+
+				if previous == Some(true) && !wall {
+					row.start = slope(row.depth, col);
+				}
+
+				if previous == Some(false) && wall {
+					let mut next = row.next();
+					next.end = slope(row.depth, col);
+					rows.push(next);
+				}
+
+				previous = Some(wall);
+			}
+
+			if previous == Some(false) {
+				rows.push(row.next());
+			}
+		}
+	}
+
+	visible
+}
+
+/**
+ * Can something on from see to? Symmetric, if from can see to then to can see from.
+ */
+pub fn line_of_sight(map: &Map, from: TilePos, to: TilePos) -> bool {
+	let (u_0, v_0) = Map::to_diamond(from);
+	let (u_1, v_1) = Map::to_diamond(to);
+	let radius = (u_1 - u_0).abs() + (v_1 - v_0).abs();
+
+	field_of_view(map, from, radius).contains(&to)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use map::{Map, Tile, TileDef, TileRegistry};
+	use map::gen::Rng;
+
+	const WALL: Tile = Tile { x: 1, y: 0 };
+
+	fn map() -> Map {
+		let mut map = Map::new(16, 32);
+		map.registry = TileRegistry {
+			tiles: vec![TileDef {
+				x: WALL.x,
+				y: WALL.y,
+				name: "wall".to_string(),
+				walkable: false,
+				cost: 1.0,
+				blocks_sight: true,
+				damage: 0
+			}]
+		};
+		map
+	}
+
+	fn wall(map: &mut Map, (x, y): TilePos) {
+		let idx = map.idx(x, y);
+		map.layers[0][idx] = Some(WALL);
+	}
+
+	/**
+	 * The tile steps along the diamond axes from pos
+	 */
+	fn along(map: &Map, pos: TilePos, d_u: isize, d_v: isize) -> TilePos {
+		let (u, v) = Map::to_diamond(pos);
+		map.from_diamond((u + d_u, v + d_v)).unwrap()
+	}
+
+	#[test]
+	fn sight_is_symmetric() {
+		let mut map = map();
+		let mut rng = Rng::new(3);
+
+		for y in 0..map.height {
+			for x in 0..map.width {
+				if rng.chance(0.2) {
+					wall(&mut map, (x, y));
+				}
+			}
+		}
+
+		let open: Vec<TilePos> = (0..map.height)
+			.flat_map(|y| (0..map.width).map(move |x| (x, y)))
+			.filter(|&(x, y)| !map.blocks_sight(x, y))
+			.collect();
+
+		let mut seen = 0;
+
+		let every = |step: usize| open.iter().enumerate().filter(move |&(i, _)| i % step == 0).map(|(_, &tile)| tile);
+
+		for a in every(3) {
+			for b in every(5) {
+				let there = line_of_sight(&map, a, b);
+				assert_eq!(there, line_of_sight(&map, b, a), "{:?} and {:?}", a, b);
+
+				if there && a != b {
+					seen += 1;
+				}
+			}
+		}
+
+		//Not symmetric by seeing nothing at all
+		assert!(seen > 100, "only {} pairs could see each other", seen);
+	}
+
+	#[test]
+	fn walls_hide_what_is_behind_them() {
+		let mut map = map();
+		let origin = (8, 16);
+		let blocking = along(&map, origin, 2, 0);
+		let behind = along(&map, origin, 4, 0);
+
+		assert!(field_of_view(&map, origin, VIEW_RADIUS).contains(&behind));
+
+		wall(&mut map, blocking);
+		let visible = field_of_view(&map, origin, VIEW_RADIUS);
+
+		assert!(visible.contains(&blocking));
+		assert!(!visible.contains(&behind));
+		assert!(!line_of_sight(&map, origin, behind));
+		assert!(visible.contains(&along(&map, origin, -4, 0)));
+	}
+
+	#[test]
+	fn nothing_past_the_radius_is_revealed() {
+		let map = map();
+		let origin = (8, 16);
+		let (u, v) = Map::to_diamond(origin);
+		let radius = 4;
+		let visible = field_of_view(&map, origin, radius);
+
+		for &tile in &visible {
+			let (t_u, t_v) = Map::to_diamond(tile);
+			let (d_u, d_v) = (t_u - u, t_v - v);
+			assert!(d_u * d_u + d_v * d_v <= radius * radius, "{:?} is outside the radius", tile);
+		}
+
+		assert!(visible.contains(&along(&map, origin, radius, 0)));
+		assert!(!visible.contains(&along(&map, origin, radius + 1, 0)));
+		assert!(!visible.contains(&along(&map, origin, 3, 3)));
+	}
+}
@@ -1,11 +1,16 @@
 extern crate rustc_serialize;
+extern crate bincode;
 
 mod world;
 pub mod entity;
 pub mod utils;
 pub mod math;
 pub mod message;
-mod map;
+pub mod map;
+pub mod path;
+pub mod fov;
+pub mod collision;
+pub mod schema;
 
 pub use world::World;
 pub use entity::Entity;
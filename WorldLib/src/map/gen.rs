@@ -0,0 +1,543 @@
+use std::collections::VecDeque;
+use map::{Map, Tile, Spawn};
+use path::TilePos;
+
+/**
+ * Tiles from the grass tileset, see grass.tiles for what each one does
+ */
+pub const GRASS: Tile = Tile { x: 0, y: 0 };
+pub const STONY_GRASS: Tile = Tile { x: 0, y: 1 };
+pub const CLIFF: Tile = Tile { x: 0, y: 2 };
+pub const WATER: Tile = Tile { x: 0, y: 18 };
+pub const SHALLOW_WATER: Tile = Tile { x: 0, y: 19 };
+
+/**
+ * Terrain heights below each bound get its tile, anything higher is cliff
+ */
+const BANDS: [(f64, Tile); 4] = [(0.32, WATER), (0.37, SHALLOW_WATER), (0.62, GRASS), (0.72, STONY_GRASS)];
+
+/**
+ * Tiles across one cell of the noise lattice
+ */
+const TERRAIN_SCALE: f64 = 8.0;
+const OCTAVES: u32 = 4;
+
+/**
+ * Chance a cave tile starts as wall and how many times the automaton is run
+ */
+const CAVE_FILL: f64 = 0.45;
+const CAVE_STEPS: usize = 5;
+
+const ROOM_ATTEMPTS: usize = 40;
+const ROOM_MIN: usize = 3;
+const ROOM_MAX: usize = 6;
+
+/**
+ * How many other spawns terrain and caves get besides the player's
+ */
+const SPAWNS: usize = 4;
+
+/**
+ * The kinds of map that can be generated, a generated map can be stored as
+ * just its generator, size and seed
+ */
+#[derive(RustcEncodable, RustcDecodable, Debug, Clone, Copy, PartialEq)]
+pub enum Generator {
+	Terrain,
+	Caves,
+	Dungeon
+}
+
+impl Generator {
+	pub fn from_name(name: &str) -> Option<Generator> {
+		match name {
+			"terrain" => Some(Generator::Terrain),
+			"caves" => Some(Generator::Caves),
+			"dungeon" => Some(Generator::Dungeon),
+			_ => None
+		}
+	}
+}
+
+/**
+ * Every generated map has a player spawn on open ground
+ */
+pub fn generate(generator: Generator, width: usize, height: usize, seed: u64) -> Map {
+	let mut map = match generator {
+		Generator::Terrain => terrain(width, height, seed),
+		Generator::Caves => caves(width, height, seed),
+		Generator::Dungeon => dungeon(width, height, seed)
+	};
+
+	ensure_player_spawn(&mut map);
+	map
+}
+
+/**
+ * splitmix64, small and fully determined by its seed so maps come out the
+ * same on every machine
+ */
+#[derive(Debug, Clone)]
+pub struct Rng {
+	state: u64
+}
+
+impl Rng {
+
+	pub fn new(seed: u64) -> Rng {
+		Rng {
+			state: seed
+		}
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+		z ^ (z >> 31)
+	}
+
+	/**
+	 * Uniform in [0, 1)
+	 */
+	pub fn next_f64(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
+
+	/**
+	 * Uniform in [low, high), high must be greater than low
+	 */
+	pub fn range(&mut self, low: usize, high: usize) -> usize {
+		low + (self.next_u64() % (high - low) as u64) as usize
+	}
+
+	pub fn chance(&mut self, p: f64) -> bool {
+		self.next_f64() < p
+	}
+}
+
+/**
+ * Rolling hills with lakes, each tile's height picks its tile from BANDS
+ */
+pub fn terrain(width: usize, height: usize, seed: u64) -> Map {
+	let mut map = filled(width, height, GRASS);
+
+	for y in 0..height {
+		for x in 0..width {
+			//Sample where the tile is drawn so the stagger doesn't show in the noise
+			let pos = map.tile_to_world(x, y);
+			let (across, down) = (pos.x / map.tile_width, pos.y / (map.tile_height * 2.0));
+			let elevation = fractal(seed, across / TERRAIN_SCALE, down / TERRAIN_SCALE);
+
+			let tile = BANDS.iter()
+				.find(|&&(below, _)| elevation < below)
+				.map(|&(_, tile)| tile)
+				.unwrap_or(CLIFF);
+
+			set(&mut map, (x, y), tile);
+		}
+	}
+
+	let mut rng = Rng::new(seed);
+	let region = largest_region(&map);
+	map.spawns = scatter(&mut rng, &region, &map);
+	map
+}
+
+/**
+ * Caves grown by a cellular automaton, pockets that can't be reached from
+ * the largest cave are filled in
+ */
+pub fn caves(width: usize, height: usize, seed: u64) -> Map {
+	let mut rng = Rng::new(seed);
+	let mut map = filled(width, height, STONY_GRASS);
+	let mut walls: Vec<bool> = (0..(width * height)).map(|_| rng.chance(CAVE_FILL)).collect();
+
+	for _ in 0..CAVE_STEPS {
+		walls = (0..(width * height)).map(|idx| {
+			let pos = (idx % width, idx / width);
+
+			//Off the map counts as wall so caves close up at the edges
+			let around = surrounding(&map, pos).iter()
+				.filter(|tile| tile.map(|(x, y)| walls[map.idx(x, y)]).unwrap_or(true))
+				.count();
+
+			around >= 5 || (walls[idx] && around >= 4)
+		}).collect();
+	}
+
+	for y in 0..height {
+		for x in 0..width {
+			if walls[map.idx(x, y)] {
+				set(&mut map, (x, y), CLIFF);
+			}
+		}
+	}
+
+	let region = largest_region(&map);
+	let mut connected = vec![false; width * height];
+
+	for &(x, y) in &region {
+		connected[map.idx(x, y)] = true;
+	}
+
+	for y in 0..height {
+		for x in 0..width {
+			if !connected[map.idx(x, y)] {
+				set(&mut map, (x, y), CLIFF);
+			}
+		}
+	}
+
+	map.spawns = scatter(&mut rng, &region, &map);
+	map
+}
+
+/**
+ * A room in tile coordinates
+ */
+#[derive(Debug, Clone, Copy)]
+struct Room {
+	x: usize,
+	y: usize,
+	width: usize,
+	height: usize
+}
+
+impl Room {
+	fn centre(&self) -> TilePos {
+		(self.x + self.width / 2, self.y + self.height / 2)
+	}
+
+	/**
+	 * Rooms keep at least a tile of wall between them
+	 */
+	fn overlaps(&self, other: &Room) -> bool {
+		self.x <= other.x + other.width && other.x <= self.x + self.width &&
+			self.y <= other.y + other.height && other.y <= self.y + self.height
+	}
+}
+
+/**
+ * Rooms joined one after another by winding corridors. The player spawns in
+ * the first room and an npc in each of the others. A map too small for any
+ * room is left solid, generate then opens a tile for the player.
+ */
+pub fn dungeon(width: usize, height: usize, seed: u64) -> Map {
+	let mut rng = Rng::new(seed);
+	let mut map = filled(width, height, CLIFF);
+	let mut rooms: Vec<Room> = Vec::new();
+
+	for _ in 0..ROOM_ATTEMPTS {
+		//Rows are half a tile tall so rooms need twice as many to look square
+		let room_width = rng.range(ROOM_MIN, ROOM_MAX + 1);
+		let room_height = rng.range(ROOM_MIN * 2, ROOM_MAX * 2 + 1);
+
+		if room_width + 2 > width || room_height + 2 > height {
+			continue;
+		}
+
+		let room = Room {
+			x: rng.range(1, width - room_width),
+			y: rng.range(1, height - room_height),
+			width: room_width,
+			height: room_height
+		};
+
+		if rooms.iter().any(|other| room.overlaps(other)) {
+			continue;
+		}
+
+		for y in room.y..(room.y + room.height) {
+			for x in room.x..(room.x + room.width) {
+				set(&mut map, (x, y), GRASS);
+			}
+		}
+
+		if let Some(last) = rooms.last() {
+			corridor(&mut map, &mut rng, last.centre(), room.centre());
+		}
+
+		rooms.push(room);
+	}
+
+	map.spawns = rooms.iter().enumerate().map(|(i, room)| {
+		let (x, y) = room.centre();
+
+		if i == 0 {
+			spawn("start", "player", &map, (x, y))
+		} else {
+			spawn(&format!("room {}", i), "npc", &map, (x, y))
+		}
+	}).collect();
+
+	map
+}
+
+/**
+ * Dig from one tile to another stepping along the diamond axes, so every
+ * step crosses an edge, picking at random between the two directions that
+ * head towards the goal. The corridor is two diamonds wide to fit a body
+ * and only digs through rock.
+ */
+fn corridor(map: &mut Map, rng: &mut Rng, from: TilePos, to: TilePos) {
+	let (mut u, mut v) = Map::to_diamond(from);
+	let goal = Map::to_diamond(to);
+
+	loop {
+		for &(du, dv) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+			match map.from_diamond((u + du, v + dv)) {
+				Some(tile) if !open(map, tile) => set(map, tile, STONY_GRASS),
+				_ => {}
+			}
+		}
+
+		if (u, v) == goal {
+			break;
+		}
+
+		let step_u = (u + (goal.0 - u).signum(), v);
+		let step_v = (u, v + (goal.1 - v).signum());
+
+		let options: Vec<(isize, isize)> = [step_u, step_v].iter()
+			.cloned()
+			.filter(|&step| step != (u, v) && map.from_diamond(step).is_some())
+			.collect();
+
+		let next = match options.len() {
+			0 => if u != goal.0 { step_u } else { step_v },
+			len => options[rng.range(0, len)]
+		};
+
+		u = next.0;
+		v = next.1;
+	}
+}
+
+fn filled(width: usize, height: usize, tile: Tile) -> Map {
+	let mut map = Map::new(width, height);
+	map.layers[0] = vec![Some(tile); width * height];
+	map
+}
+
+fn set(map: &mut Map, (x, y): TilePos, tile: Tile) {
+	let idx = map.idx(x, y);
+	map.layers[0][idx] = Some(tile);
+}
+
+/**
+ * Can a generated tile be walked on? The map's registry isn't loaded yet
+ */
+fn open(map: &Map, (x, y): TilePos) -> bool {
+	match map.layers[0][map.idx(x, y)] {
+		Some(tile) => tile != CLIFF && tile != WATER,
+		None => false
+	}
+}
+
+/**
+ * The eight tiles around pos on the diamond grid, None where that is off the map
+ */
+fn surrounding(map: &Map, pos: TilePos) -> [Option<TilePos>; 8] {
+	let (u, v) = Map::to_diamond(pos);
+
+	[
+		map.from_diamond((u - 1, v - 1)), map.from_diamond((u - 1, v)), map.from_diamond((u - 1, v + 1)),
+		map.from_diamond((u, v - 1)), map.from_diamond((u, v + 1)),
+		map.from_diamond((u + 1, v - 1)), map.from_diamond((u + 1, v)), map.from_diamond((u + 1, v + 1))
+	]
+}
+
+/**
+ * The biggest group of open tiles joined by their edges
+ */
+fn largest_region(map: &Map) -> Vec<TilePos> {
+	let mut seen = vec![false; map.width * map.height];
+	let mut largest = Vec::new();
+
+	for y in 0..map.height {
+		for x in 0..map.width {
+			if seen[map.idx(x, y)] || !open(map, (x, y)) {
+				continue;
+			}
+
+			let mut region = Vec::new();
+			let mut queue = VecDeque::new();
+			seen[map.idx(x, y)] = true;
+			queue.push_back((x, y));
+
+			while let Some(pos) = queue.pop_front() {
+				region.push(pos);
+				let (u, v) = Map::to_diamond(pos);
+
+				for &next in [(u - 1, v), (u + 1, v), (u, v - 1), (u, v + 1)].iter() {
+					if let Some(tile) = map.from_diamond(next) {
+						let idx = map.idx(tile.0, tile.1);
+
+						if !seen[idx] && open(map, tile) {
+							seen[idx] = true;
+							queue.push_back(tile);
+						}
+					}
+				}
+			}
+
+			if region.len() > largest.len() {
+				largest = region;
+			}
+		}
+	}
+
+	largest
+}
+
+fn spawn(name: &str, kind: &str, map: &Map, (x, y): TilePos) -> Spawn {
+	Spawn {
+		name: name.to_string(),
+		kind: kind.to_string(),
+		pos: map.tile_to_world(x, y)
+	}
+}
+
+/**
+ * Open up the middle of a map with nowhere to stand, such as terrain that is
+ * all water or a dungeon too small for a room, and spawn the player there.
+ * A map without any tiles is left alone.
+ */
+fn ensure_player_spawn(map: &mut Map) {
+	if map.player_spawn().is_some() || !map.contains(0, 0) {
+		return;
+	}
+
+	let centre = (map.width / 2, map.height / 2);
+	set(map, centre, GRASS);
+
+	let start = spawn("start", "player", map, centre);
+	map.spawns.insert(0, start);
+}
+
+/**
+ * A player spawn and a few npc spawns on random tiles of region
+ */
+fn scatter(rng: &mut Rng, region: &[TilePos], map: &Map) -> Vec<Spawn> {
+	if region.is_empty() {
+		return Vec::new();
+	}
+
+	let mut spawns = vec![spawn("start", "player", map, region[rng.range(0, region.len())])];
+
+	for i in 0..SPAWNS {
+		spawns.push(spawn(&format!("npc {}", i + 1), "npc", map, region[rng.range(0, region.len())]));
+	}
+
+	spawns
+}
+
+/**
+ * Smooth random values on a lattice, in [0, 1)
+ */
+fn value_noise(seed: u64, x: f64, y: f64) -> f64 {
+	let (x0, y0) = (x.floor(), y.floor());
+	let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+	let (ix, iy) = (x0 as i64, y0 as i64);
+
+	let top = lerp(lattice(seed, ix, iy), lattice(seed, ix + 1, iy), tx);
+	let bottom = lerp(lattice(seed, ix, iy + 1), lattice(seed, ix + 1, iy + 1), tx);
+	lerp(top, bottom, ty)
+}
+
+/**
+ * Octaves of value noise, each twice the detail and half the strength of the last
+ */
+fn fractal(seed: u64, x: f64, y: f64) -> f64 {
+	let (mut total, mut amplitude, mut frequency, mut weight) = (0.0, 1.0, 1.0, 0.0);
+
+	for octave in 0..OCTAVES {
+		total += amplitude * value_noise(seed.wrapping_add(octave as u64), x * frequency, y * frequency);
+		weight += amplitude;
+		amplitude /= 2.0;
+		frequency *= 2.0;
+	}
+
+	total / weight
+}
+
+fn lattice(seed: u64, x: i64, y: i64) -> f64 {
+	let hash = seed ^ (x as u64).wrapping_mul(0x9E3779B97F4A7C15) ^ (y as u64).wrapping_mul(0xC2B2AE3D27D4EB4F);
+	Rng::new(hash).next_f64()
+}
+
+fn smooth(t: f64) -> f64 {
+	t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+	a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const GENERATORS: [Generator; 3] = [Generator::Terrain, Generator::Caves, Generator::Dungeon];
+
+	#[test]
+	fn the_same_seed_gives_the_same_map() {
+		for &generator in GENERATORS.iter() {
+			let first = generate(generator, 30, 60, 42);
+			let second = generate(generator, 30, 60, 42);
+
+			assert_eq!(first.layers, second.layers, "{:?}", generator);
+			assert_eq!(first.spawns, second.spawns, "{:?}", generator);
+			assert!(first.layers != generate(generator, 30, 60, 43).layers, "{:?}", generator);
+		}
+	}
+
+	#[test]
+	fn every_spawn_is_on_open_ground() {
+		for &generator in GENERATORS.iter() {
+			for seed in 0..10 {
+				let map = generate(generator, 30, 60, seed);
+
+				assert!(map.spawns.iter().any(|spawn| spawn.kind == "player"), "{:?} seed {}", generator, seed);
+
+				for spawn in &map.spawns {
+					let tile = map.world_to_tile(spawn.pos).expect("a spawn on the map");
+					assert!(open(&map, tile), "{:?} seed {} {:?}", generator, seed, spawn);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn tiny_maps_are_generated() {
+		for &generator in GENERATORS.iter() {
+			for &(width, height) in [(3, 3), (1, 1), (1, 6)].iter() {
+				let map = generate(generator, width, height, 7);
+				assert_eq!(map.validate(), Ok(()), "{:?} {}x{}", generator, width, height);
+
+				let tile = map.player_spawn().and_then(|pos| map.world_to_tile(pos)).expect("a player spawn on the map");
+				assert!(open(&map, tile), "{:?} {}x{}", generator, width, height);
+			}
+		}
+	}
+
+	#[test]
+	fn maps_with_nowhere_to_stand_get_a_spawn() {
+		let mut map = filled(5, 8, WATER);
+		ensure_player_spawn(&mut map);
+
+		assert_eq!(map.spawns.len(), 1);
+		assert_eq!(map.world_to_tile(map.player_spawn().unwrap()), Some((2, 4)));
+		assert!(open(&map, (2, 4)));
+
+		let spawns = map.spawns.clone();
+		ensure_player_spawn(&mut map);
+		assert_eq!(map.spawns, spawns);
+
+		let mut empty = Map::new(0, 0);
+		ensure_player_spawn(&mut empty);
+		assert!(empty.spawns.is_empty());
+	}
+}
@@ -1,12 +1,96 @@
-use rustc_serialize::json;
+use schema;
+use schema::{Kind, SchemaError};
+use math::{Vec2, Rect};
 
-#[derive(RustcEncodable, RustcDecodable, Debug, Clone, Copy)]
+mod tiles;
+pub mod tiled;
+pub mod gen;
+
+pub use self::tiles::{TileDef, TileRegistry};
+pub use self::tiled::TiledError;
+pub use self::gen::Generator;
+
+#[derive(RustcEncodable, RustcDecodable, Debug, Clone, Copy, PartialEq)]
 pub struct Tile {
 	pub x: usize,
 	pub y: usize
 }
 
-pub type Layer = Vec<Tile>;
+/**
+ * One tile per map position, None where the layer is empty
+ */
+pub type Layer = Vec<Option<Tile>>;
+
+/**
+ * The tileset image a map is drawn from, columns and count describe its grid of tiles
+ */
+#[derive(RustcEncodable, RustcDecodable, Debug, Clone, PartialEq)]
+pub struct TilesetRef {
+	pub name: String,
+	pub columns: usize,
+	pub count: usize
+}
+
+/**
+ * A named point placed in the map editor, such as where players appear
+ */
+#[derive(RustcEncodable, RustcDecodable, Debug, Clone, PartialEq)]
+pub struct Spawn {
+	pub name: String,
+	pub kind: String,
+	pub pos: Vec2
+}
+
+/**
+ * A region that sends anything stepping into it to target in the zone named zone
+ */
+#[derive(RustcEncodable, RustcDecodable, Debug, Clone, PartialEq)]
+pub struct Portal {
+	pub area: Rect,
+	pub zone: String,
+	pub target: Vec2
+}
+
+/**
+ * A change to a single tile of a single layer
+ */
+#[derive(RustcEncodable, RustcDecodable, Debug, Clone, Copy)]
+pub struct TileEdit {
+	pub layer: usize,
+	pub x: usize,
+	pub y: usize,
+	pub tile: Tile
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditError {
+	NoSuchLayer,
+	OutOfBounds
+}
+
+/**
+ * The most tiles along either side of a map accepted from elsewhere, keeps
+ * everything sized by the map such as path finding to a sensible allocation
+ */
+pub const MAX_MAP_SIZE: usize = 1024;
+
+/**
+ * Why a map can't be used
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapError {
+	//Width and height must each be from 1 to MAX_MAP_SIZE
+	BadSize(usize, usize),
+
+	//The layer at this index doesn't have exactly one entry per tile
+	BadLayer(usize),
+
+	//Tiles must have a positive, finite width and height
+	BadTileSize,
+
+	//The tileset has no columns to find tiles in
+	NoColumns
+}
 
 #[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
 pub struct Map {
@@ -14,18 +98,30 @@ pub struct Map {
 	pub width: usize,
 	pub height: usize,
 	pub tile_width: f64,
-	pub tile_height: f64
+	pub tile_height: f64,
+	pub tileset: TilesetRef,
+	pub spawns: Vec<Spawn>,
+	pub portals: Vec<Portal>,
+	pub registry: TileRegistry
 }
 
 impl Map {
 
 	pub fn new(w: usize, h: usize) -> Map {
 		Map {
-			layers: [(0..(w * h)).map(|_| Tile {x: 0, y: 0}).collect()].to_vec(),
+			layers: [(0..(w * h)).map(|_| Some(Tile {x: 0, y: 0})).collect()].to_vec(),
 			width: w,
 			height: h,
 			tile_width: 64.0,
-			tile_height: 16.0
+			tile_height: 16.0,
+			tileset: TilesetRef {
+				name: "grass".to_string(),
+				columns: 16,
+				count: 672
+			},
+			spawns: Vec::new(),
+			portals: Vec::new(),
+			registry: TileRegistry::new()
 		}
 	}
 
@@ -33,11 +129,411 @@ impl Map {
 		(y * self.width) + x
 	}
 
+	pub fn contains(&self, x: usize, y: usize) -> bool {
+		x < self.width && y < self.height
+	}
+
+	/**
+	 * Where players enter this map, the first spawn of kind "player"
+	 */
+	pub fn player_spawn(&self) -> Option<Vec2> {
+		self.spawns.iter().find(|spawn| spawn.kind == "player").map(|spawn| spawn.pos)
+	}
+
+	/**
+	 * Every layers tile at (x, y), bottom layer first
+	 */
+	pub fn tiles_at(&self, x: usize, y: usize) -> Vec<Tile> {
+		let idx = self.idx(x, y);
+		self.layers.iter().filter_map(|layer| layer.get(idx).and_then(|&tile| tile)).collect()
+	}
+
+	/**
+	 * Can something stand on (x, y)? Every layer has to be walkable
+	 */
+	pub fn walkable(&self, x: usize, y: usize) -> bool {
+		self.contains(x, y) && self.tiles_at(x, y).iter().all(|&tile| self.registry.walkable(tile))
+	}
+
+	/**
+	 * The cost of moving onto (x, y), the most expensive layer wins. None if it can't be entered.
+	 */
+	pub fn movement_cost(&self, x: usize, y: usize) -> Option<f64> {
+		if !self.walkable(x, y) {
+			return None;
+		}
+
+		Some(self.tiles_at(x, y).iter().fold(1.0, |cost, &tile| cost.max(self.registry.cost(tile))))
+	}
+
+	/**
+	 * Tiles outside the map block sight
+	 */
+	pub fn blocks_sight(&self, x: usize, y: usize) -> bool {
+		!self.contains(x, y) || self.tiles_at(x, y).iter().any(|&tile| self.registry.blocks_sight(tile))
+	}
+
+	/**
+	 * Damage dealt to anything standing on (x, y)
+	 */
+	pub fn damage(&self, x: usize, y: usize) -> i32 {
+		if !self.contains(x, y) {
+			return 0;
+		}
+
+		self.tiles_at(x, y).iter().map(|&tile| self.registry.damage(tile)).sum()
+	}
+
+	/**
+	 * Where the tiles image is drawn, odd rows are offset by half a tile
+	 */
+	pub fn tile_origin(&self, x: usize, y: usize) -> Vec2 {
+		let stagger = if y % 2 == 0 { 0.0 } else { self.tile_width / 2.0 };
+		Vec2::new((x as f64 * self.tile_width) + stagger, y as f64 * self.tile_height)
+	}
+
+	/**
+	 * Centre of the tiles diamond in world units, tile images are twice as tall as a row
+	 */
+	pub fn tile_to_world(&self, x: usize, y: usize) -> Vec2 {
+		self.tile_origin(x, y) + Vec2::new(self.tile_width / 2.0, self.tile_height)
+	}
+
+	/**
+	 * Coordinates on the grid of diamonds, where tiles sharing an edge differ by
+	 * one along a single axis. u runs down and right, v up and right.
+	 */
+	pub fn to_diamond((x, y): (usize, usize)) -> (isize, isize) {
+		let across = (2 * x + y % 2) as isize;
+		let down = y as isize;
+		((across + down) / 2, (across - down) / 2)
+	}
+
+	/**
+	 * The tile at a diamond coordinate, None if it's off the map
+	 */
+	pub fn from_diamond(&self, (u, v): (isize, isize)) -> Option<(usize, usize)> {
+		let y = u - v;
+		let twice_x = u + v - if y % 2 == 0 { 0 } else { 1 };
+
+		if y < 0 || twice_x < 0 || !self.contains((twice_x / 2) as usize, y as usize) {
+			None
+		} else {
+			Some(((twice_x / 2) as usize, y as usize))
+		}
+	}
+
+	/**
+	 * The tile whose diamond contains pos, which may lie off the map
+	 */
+	fn pick(&self, pos: Vec2) -> (isize, isize) {
+		let across = pos.x / (self.tile_width / 2.0);
+		let down = pos.y / self.tile_height;
+
+		//Rotated 45 degrees the diamonds become squares centred on even points
+		let a = 2 * ((across + down) / 2.0).round() as isize;
+		let b = 2 * ((across - down) / 2.0).round() as isize;
+
+		let y = (a - b) / 2 - 1;
+		let stagger = if y % 2 == 0 { 0 } else { 1 };
+		let twice_x = (a + b) / 2 - 1 - stagger;
+
+		//Floor rather than truncate so tiles left of the map stay negative
+		let x = if twice_x < 0 { (twice_x - 1) / 2 } else { twice_x / 2 };
+		(x, y)
+	}
+
+	/**
+	 * The tile under a point in world units, None if the point is off the map
+	 */
+	pub fn world_to_tile(&self, pos: Vec2) -> Option<(usize, usize)> {
+		let (x, y) = self.pick(pos);
+
+		if x < 0 || y < 0 || !self.contains(x as usize, y as usize) {
+			None
+		} else {
+			Some((x as usize, y as usize))
+		}
+	}
+
+	/**
+	 * The tile under a point, or the closest tile on the edge of the map if it's off the map
+	 */
+	pub fn world_to_nearest_tile(&self, pos: Vec2) -> (usize, usize) {
+		let (x, y) = self.pick(pos);
+		let clamp = |v: isize, len: usize| if v < 0 { 0 } else if v as usize >= len { len - 1 } else { v as usize };
+		(clamp(x, self.width), clamp(y, self.height))
+	}
+
+	/**
+	 * The size of the drawn map in world units, odd rows are offset by half a tile
+	 * and each tile image is twice the row height
+	 */
+	pub fn world_size(&self) -> Vec2 {
+		Vec2::new((self.width as f64 * self.tile_width) + (self.tile_width / 2.0), (self.height as f64 + 1.0) * self.tile_height)
+	}
+
+	pub fn bounds(&self) -> Rect {
+		Rect::new(Vec2::zero(), self.world_size())
+	}
+
+	/**
+	 * Check a box lies entirely within the map
+	 */
+	pub fn in_bounds(&self, rect: &Rect) -> bool {
+		self.bounds().encloses(rect)
+	}
+
+	/**
+	 * The portal covering a point, the first one wins where they overlap
+	 */
+	pub fn portal_at(&self, pos: Vec2) -> Option<&Portal> {
+		self.portals.iter().find(|portal| portal.area.contains(pos))
+	}
+
+	/**
+	 * Check a map from elsewhere, such as a client or a file, is safe to use.
+	 * Everything else assumes a map passed this.
+	 */
+	pub fn validate(&self) -> Result<(), MapError> {
+		if self.width == 0 || self.height == 0 || self.width > MAX_MAP_SIZE || self.height > MAX_MAP_SIZE {
+			return Err(MapError::BadSize(self.width, self.height));
+		}
+
+		if let Some(layer) = self.layers.iter().position(|layer| layer.len() != self.width * self.height) {
+			return Err(MapError::BadLayer(layer));
+		}
+
+		let good = |size: f64| size.is_finite() && size > 0.0;
+
+		if !good(self.tile_width) || !good(self.tile_height) {
+			return Err(MapError::BadTileSize);
+		}
+
+		if self.tileset.columns == 0 {
+			return Err(MapError::NoColumns);
+		}
+
+		Ok(())
+	}
+
+	pub fn check_edit(&self, edit: &TileEdit) -> Result<(), EditError> {
+		if edit.layer >= self.layers.len() {
+			Err(EditError::NoSuchLayer)
+		} else if edit.x >= self.width || edit.y >= self.height || self.idx(edit.x, edit.y) >= self.layers[edit.layer].len() {
+			Err(EditError::OutOfBounds)
+		} else {
+			Ok(())
+		}
+	}
+
+	pub fn apply(&mut self, edit: &TileEdit) -> Result<(), EditError> {
+		try!(self.check_edit(edit));
+		let idx = self.idx(edit.x, edit.y);
+		self.layers[edit.layer][idx] = Some(edit.tile);
+		Ok(())
+	}
+
+	/**
+	 * Apply a batch of edits, nothing is changed unless every edit is valid
+	 */
+	pub fn apply_all(&mut self, edits: &[TileEdit]) -> Result<(), EditError> {
+		for edit in edits {
+			try!(self.check_edit(edit));
+		}
+
+		for edit in edits {
+			try!(self.apply(edit));
+		}
+
+		Ok(())
+	}
+
 	pub fn as_json(&self) -> String {
-		json::encode(&self).unwrap()
+		schema::encode(Kind::Map, self).unwrap()
+	}
+
+	/**
+	 * Load a saved map, upgrading it if it was saved by an older version
+	 */
+	pub fn from_json(data: &str) -> Result<Map, SchemaError> {
+		schema::decode(Kind::Map, data)
+	}
+
+	/**
+	 * Load a map made in the Tiled editor, see tiled::import
+	 */
+	pub fn from_tiled(data: &str) -> Result<Map, TiledError> {
+		tiled::import(data)
+	}
+
+	/**
+	 * Generate a map, the same generator, size and seed always give the same map
+	 */
+	pub fn generate(generator: Generator, width: usize, height: usize, seed: u64) -> Map {
+		gen::generate(generator, width, height, seed)
+	}
+
+	/**
+	 * Save a map for editing in Tiled, see tiled::export
+	 */
+	pub fn as_tiled(&self) -> Result<String, MapError> {
+		tiled::export(self)
+	}
+}
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use math::Vec2;
+
+	//Deliberately not square so swapped axes show up
+	const WIDTH: usize = 7;
+	const HEIGHT: usize = 10;
+
+	fn map() -> Map {
+		Map::new(WIDTH, HEIGHT)
+	}
+
+	#[test]
+	fn every_tile_round_trips() {
+		let map = map();
+
+		for y in 0..HEIGHT {
+			for x in 0..WIDTH {
+				assert_eq!(map.world_to_tile(map.tile_to_world(x, y)), Some((x, y)));
+			}
+		}
 	}
 
-	pub fn from_json(data: &str) -> Map {
-		json::decode(data).unwrap()
+	#[test]
+	fn points_near_each_vertex_stay_in_the_tile() {
+		let map = map();
+		let inset = 1.0;
+		let half = Vec2::new(map.tile_width / 2.0, map.tile_height);
+
+		for y in 0..HEIGHT {
+			for x in 0..WIDTH {
+				let centre = map.tile_to_world(x, y);
+
+				let vertices = [
+					centre + Vec2::new(half.x - inset, 0.0),
+					centre - Vec2::new(half.x - inset, 0.0),
+					centre + Vec2::new(0.0, half.y - inset),
+					centre - Vec2::new(0.0, half.y - inset)
+				];
+
+				for &vertex in vertices.iter() {
+					assert_eq!(map.world_to_tile(vertex), Some((x, y)), "near a vertex of {:?}", (x, y));
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn points_past_each_vertex_are_in_the_next_tile() {
+		let map = map();
+		let past = 1.0;
+		let half = Vec2::new(map.tile_width / 2.0, map.tile_height);
+
+		for y in 0..HEIGHT {
+			for x in 0..WIDTH {
+				let centre = map.tile_to_world(x, y);
+
+				//The tiles meeting at each vertex of a staggered diamond are a column or two rows away
+				let beyond = [
+					(centre + Vec2::new(half.x + past, 0.0), (x as isize + 1, y as isize)),
+					(centre - Vec2::new(half.x + past, 0.0), (x as isize - 1, y as isize)),
+					(centre + Vec2::new(0.0, half.y + past), (x as isize, y as isize + 2)),
+					(centre - Vec2::new(0.0, half.y + past), (x as isize, y as isize - 2))
+				];
+
+				for &(point, (nx, ny)) in beyond.iter() {
+					let expected = if nx < 0 || ny < 0 || !map.contains(nx as usize, ny as usize) {
+						None
+					} else {
+						Some((nx as usize, ny as usize))
+					};
+
+					assert_eq!(map.world_to_tile(point), expected, "past a vertex of {:?}", (x, y));
+				}
+			}
+		}
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn points_off_the_map_have_no_tile() {
+		let map = map();
+		let size = map.world_size();
+
+		let off = [
+			Vec2::new(-1.0, -1.0),
+			Vec2::new(-100.0, 50.0),
+			Vec2::new(50.0, -100.0),
+			Vec2::new(-0.5, size.y / 2.0),
+			Vec2::new(size.x + 1.0, size.y / 2.0),
+			Vec2::new(size.x / 2.0, size.y + 1.0),
+			Vec2::new(size.x * 10.0, size.y * 10.0),
+
+			//Inside the drawn bounds but in the gaps between diamonds around the edge
+			Vec2::new(1.0, 1.0),
+			Vec2::new(size.x - 1.0, size.y - 1.0)
+		];
+
+		for &point in off.iter() {
+			assert_eq!(map.world_to_tile(point), None, "at {:?}", point);
+		}
+	}
+
+	#[test]
+	fn nearest_tile_clamps_to_the_edge() {
+		let map = map();
+
+		assert_eq!(map.world_to_nearest_tile(Vec2::new(-500.0, -500.0)), (0, 0));
+		assert_eq!(map.world_to_nearest_tile(Vec2::new(1e6, 1e6)), (WIDTH - 1, HEIGHT - 1));
+		assert_eq!(map.world_to_nearest_tile(map.tile_to_world(3, 4)), (3, 4));
+	}
+
+	#[test]
+	fn validates_sizes() {
+		assert_eq!(map().validate(), Ok(()));
+
+		let mut short = map();
+		short.layers.push(vec![None; WIDTH * HEIGHT - 1]);
+		assert_eq!(short.validate(), Err(MapError::BadLayer(1)));
+
+		let mut huge = map();
+		huge.width = MAX_MAP_SIZE + 1;
+		assert_eq!(huge.validate(), Err(MapError::BadSize(MAX_MAP_SIZE + 1, HEIGHT)));
+
+		let mut empty = map();
+		empty.height = 0;
+		assert_eq!(empty.validate(), Err(MapError::BadSize(WIDTH, 0)));
+
+		let mut flat = map();
+		flat.tile_height = 0.0;
+		assert_eq!(flat.validate(), Err(MapError::BadTileSize));
+	}
+
+	#[test]
+	fn edits_past_the_end_of_a_short_layer_are_refused() {
+		let mut map = map();
+		map.layers.push(vec![None; 3]);
+
+		let edit = |x, y| TileEdit { layer: 1, x: x, y: y, tile: Tile { x: 1, y: 0 } };
+
+		assert_eq!(map.apply(&edit(2, 0)), Ok(()));
+		assert_eq!(map.apply(&edit(3, 0)), Err(EditError::OutOfBounds));
+		assert_eq!(map.apply(&edit(WIDTH - 1, HEIGHT - 1)), Err(EditError::OutOfBounds));
+	}
+
+	#[test]
+	fn players_enter_at_the_first_player_spawn() {
+		let mut map = map();
+		assert_eq!(map.player_spawn(), None);
+
+		let spawn = |kind: &str, x| Spawn { name: String::new(), kind: kind.to_string(), pos: Vec2::new(x, 40.0) };
+		map.spawns = vec![spawn("npc", 10.0), spawn("player", 20.0), spawn("player", 30.0)];
+
+		assert_eq!(map.player_spawn(), Some(Vec2::new(20.0, 40.0)));
+	}
+}
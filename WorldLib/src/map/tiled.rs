@@ -0,0 +1,311 @@
+use rustc_serialize::json::{Json, ParserError};
+use map::{Map, MapError, Layer, Tile, TilesetRef, Spawn, Portal, TileRegistry};
+use math::{Vec2, Rect};
+use utils::json_object;
+
+/**
+ * Tiled sets the top bits of a gid when a tile is flipped, which Map has no way to show
+ */
+const FLIP_FLAGS: u64 = 0xF0000000;
+
+#[derive(Debug)]
+pub enum TiledError {
+	Parse(ParserError),
+	Missing(&'static str),
+	Unsupported(String),
+	Invalid(MapError)
+}
+
+fn field<'a>(json: &'a Json, name: &'static str) -> Result<&'a Json, TiledError> {
+	json.find(name).ok_or(TiledError::Missing(name))
+}
+
+fn number(json: &Json, name: &'static str) -> Result<u64, TiledError> {
+	try!(field(json, name)).as_u64().ok_or(TiledError::Missing(name))
+}
+
+fn float(json: &Json, name: &'static str) -> Result<f64, TiledError> {
+	try!(field(json, name)).as_f64().ok_or(TiledError::Missing(name))
+}
+
+fn string<'a>(json: &'a Json, name: &'static str) -> Result<&'a str, TiledError> {
+	try!(field(json, name)).as_string().ok_or(TiledError::Missing(name))
+}
+
+fn array<'a>(json: &'a Json, name: &'static str) -> Result<&'a Vec<Json>, TiledError> {
+	try!(field(json, name)).as_array().ok_or(TiledError::Missing(name))
+}
+
+fn expect(json: &Json, name: &'static str, value: &str) -> Result<(), TiledError> {
+	let found = try!(string(json, name));
+
+	if found == value {
+		Ok(())
+	} else {
+		Err(TiledError::Unsupported(format!("{} {}, only {} is supported", name, found, value)))
+	}
+}
+
+/**
+ * Read a map saved by Tiled as JSON. It has to be a staggered map with odd
+ * rows shifted, like the ones we draw, and use a single embedded tileset.
+ * Every tile layer becomes a layer and the objects in object layers become spawns,
+ * except objects of type portal which become portals. A portal needs zone,
+ * target_x and target_y properties saying where it leads.
+ */
+pub fn import(data: &str) -> Result<Map, TiledError> {
+	let json = try!(Json::from_str(data).map_err(TiledError::Parse));
+
+	try!(expect(&json, "orientation", "staggered"));
+	try!(expect(&json, "staggeraxis", "y"));
+	try!(expect(&json, "staggerindex", "odd"));
+
+	let width = try!(number(&json, "width")) as usize;
+	let height = try!(number(&json, "height")) as usize;
+
+	let tilesets = try!(array(&json, "tilesets"));
+
+	let tileset = match tilesets.len() {
+		1 => &tilesets[0],
+		0 => return Err(TiledError::Missing("tilesets")),
+		_ => return Err(TiledError::Unsupported("more than one tileset".to_string()))
+	};
+
+	if tileset.find("source").is_some() {
+		return Err(TiledError::Unsupported("external tilesets, embed the tileset in the map".to_string()));
+	}
+
+	let first_gid = try!(number(tileset, "firstgid"));
+	let columns = try!(number(tileset, "columns")) as usize;
+
+	if columns == 0 {
+		return Err(TiledError::Unsupported("tilesets without columns".to_string()));
+	}
+
+	let mut map = Map {
+		layers: Vec::new(),
+		width: width,
+		height: height,
+		tile_width: try!(float(&json, "tilewidth")),
+		tile_height: try!(float(&json, "tileheight")) / 2.0,
+		tileset: TilesetRef {
+			name: try!(string(tileset, "name")).to_string(),
+			columns: columns,
+			count: try!(number(tileset, "tilecount")) as usize
+		},
+		spawns: Vec::new(),
+		portals: Vec::new(),
+		registry: TileRegistry::new()
+	};
+
+	//Before anything is sized by the map
+	try!(map.validate().map_err(TiledError::Invalid));
+
+	for layer in try!(array(&json, "layers")) {
+		match try!(string(layer, "type")) {
+			"tilelayer" => map.layers.push(try!(tile_layer(layer, width * height, first_gid, columns))),
+			"objectgroup" => {
+				for object in try!(array(layer, "objects")) {
+					if kind(object) == "portal" {
+						map.portals.push(try!(portal(object)));
+					} else {
+						map.spawns.push(try!(spawn(object)));
+					}
+				}
+			},
+			//Image and group layers have nothing a Map can hold
+			_ => {}
+		}
+	}
+
+	if map.layers.is_empty() {
+		return Err(TiledError::Missing("tile layer"));
+	}
+
+	Ok(map)
+}
+
+fn tile_layer(layer: &Json, size: usize, first_gid: u64, columns: usize) -> Result<Layer, TiledError> {
+	if layer.find("encoding").and_then(|encoding| encoding.as_string()).map(|encoding| encoding != "csv").unwrap_or(false) {
+		return Err(TiledError::Unsupported("base64 layer data, save layers as CSV".to_string()));
+	}
+
+	let data = try!(array(layer, "data"));
+
+	if data.len() != size {
+		return Err(TiledError::Unsupported("layers that are not the size of the map".to_string()));
+	}
+
+	let mut tiles = Vec::with_capacity(size);
+
+	for gid in data {
+		let gid = try!(gid.as_u64().ok_or(TiledError::Missing("data"))) & !FLIP_FLAGS;
+
+		tiles.push(if gid < first_gid {
+			None
+		} else {
+			let local = (gid - first_gid) as usize;
+			Some(Tile { x: local % columns, y: local / columns })
+		});
+	}
+
+	Ok(tiles)
+}
+
+fn kind(object: &Json) -> &str {
+	//Tiled 1.9 renamed an object's type to class
+	object.find("class").or(object.find("type")).and_then(|kind| kind.as_string()).unwrap_or("")
+}
+
+/**
+ * A custom property set on an object in Tiled
+ */
+fn property<'a>(object: &'a Json, name: &'static str) -> Result<&'a Json, TiledError> {
+	try!(array(object, "properties")).iter()
+		.find(|property| property.find("name").and_then(|found| found.as_string()) == Some(name))
+		.ok_or(TiledError::Missing(name))
+		.and_then(|property| field(property, "value"))
+}
+
+fn spawn(object: &Json) -> Result<Spawn, TiledError> {
+	Ok(Spawn {
+		name: object.find("name").and_then(|name| name.as_string()).unwrap_or("").to_string(),
+		kind: kind(object).to_string(),
+		pos: Vec2::new(try!(float(object, "x")), try!(float(object, "y")))
+	})
+}
+
+fn portal(object: &Json) -> Result<Portal, TiledError> {
+	let zone = try!(try!(property(object, "zone")).as_string().ok_or(TiledError::Missing("zone")));
+	let target_x = try!(try!(property(object, "target_x")).as_f64().ok_or(TiledError::Missing("target_x")));
+	let target_y = try!(try!(property(object, "target_y")).as_f64().ok_or(TiledError::Missing("target_y")));
+
+	Ok(Portal {
+		area: Rect::new(
+			Vec2::new(try!(float(object, "x")), try!(float(object, "y"))),
+			Vec2::new(try!(float(object, "width")), try!(float(object, "height")))
+		),
+		zone: zone.to_string(),
+		target: Vec2::new(target_x, target_y)
+	})
+}
+
+fn property_json(name: &str, kind: &str, value: Json) -> Json {
+	json_object(vec![
+		("name", Json::String(name.to_string())),
+		("type", Json::String(kind.to_string())),
+		("value", value)
+	])
+}
+
+/**
+ * Write a map as Tiled JSON with its tileset embedded, the image is expected
+ * next to the map as <tileset>.png. Fails for maps that don't validate.
+ */
+pub fn export(map: &Map) -> Result<String, MapError> {
+	try!(map.validate());
+
+	let rows = (map.tileset.count + map.tileset.columns - 1) / map.tileset.columns;
+	let (tile_width, tile_height) = (map.tile_width as u64, (map.tile_height * 2.0) as u64);
+
+	let mut layers: Vec<Json> = map.layers.iter().enumerate().map(|(i, layer)| {
+		let data = layer.iter().map(|tile| match *tile {
+			Some(tile) => Json::U64((tile.y * map.tileset.columns + tile.x) as u64 + 1),
+			None => Json::U64(0)
+		}).collect();
+
+		json_object(vec![
+			("type", Json::String("tilelayer".to_string())),
+			("name", Json::String(format!("Layer {}", i + 1))),
+			("id", Json::U64(i as u64 + 1)),
+			("x", Json::U64(0)),
+			("y", Json::U64(0)),
+			("width", Json::U64(map.width as u64)),
+			("height", Json::U64(map.height as u64)),
+			("opacity", Json::F64(1.0)),
+			("visible", Json::Boolean(true)),
+			("data", Json::Array(data))
+		])
+	}).collect();
+
+	let spawns = map.spawns.iter().enumerate().map(|(i, spawn)| json_object(vec![
+		("id", Json::U64(i as u64 + 1)),
+		("name", Json::String(spawn.name.clone())),
+		("type", Json::String(spawn.kind.clone())),
+		("x", Json::F64(spawn.pos.x)),
+		("y", Json::F64(spawn.pos.y)),
+		("width", Json::F64(0.0)),
+		("height", Json::F64(0.0)),
+		("rotation", Json::F64(0.0)),
+		("point", Json::Boolean(true)),
+		("visible", Json::Boolean(true))
+	])).collect();
+
+	let first_portal = map.spawns.len() as u64 + 1;
+
+	let portals = map.portals.iter().enumerate().map(|(i, portal)| json_object(vec![
+		("id", Json::U64(first_portal + i as u64)),
+		("name", Json::String(portal.zone.clone())),
+		("type", Json::String("portal".to_string())),
+		("x", Json::F64(portal.area.pos.x)),
+		("y", Json::F64(portal.area.pos.y)),
+		("width", Json::F64(portal.area.size.x)),
+		("height", Json::F64(portal.area.size.y)),
+		("rotation", Json::F64(0.0)),
+		("visible", Json::Boolean(true)),
+		("properties", Json::Array(vec![
+			property_json("zone", "string", Json::String(portal.zone.clone())),
+			property_json("target_x", "float", Json::F64(portal.target.x)),
+			property_json("target_y", "float", Json::F64(portal.target.y))
+		]))
+	])).collect();
+
+	let first_group = map.layers.len() as u64 + 1;
+
+	for (i, (name, objects)) in vec![("Spawns", spawns), ("Portals", portals)].into_iter().enumerate() {
+		layers.push(json_object(vec![
+			("type", Json::String("objectgroup".to_string())),
+			("name", Json::String(name.to_string())),
+			("id", Json::U64(first_group + i as u64)),
+			("x", Json::U64(0)),
+			("y", Json::U64(0)),
+			("opacity", Json::F64(1.0)),
+			("visible", Json::Boolean(true)),
+			("draworder", Json::String("topdown".to_string())),
+			("objects", Json::Array(objects))
+		]));
+	}
+
+	let tileset = json_object(vec![
+		("firstgid", Json::U64(1)),
+		("name", Json::String(map.tileset.name.clone())),
+		("image", Json::String(format!("{}.png", map.tileset.name))),
+		("imagewidth", Json::U64(map.tileset.columns as u64 * tile_width)),
+		("imageheight", Json::U64(rows as u64 * tile_height)),
+		("tilewidth", Json::U64(tile_width)),
+		("tileheight", Json::U64(tile_height)),
+		("columns", Json::U64(map.tileset.columns as u64)),
+		("tilecount", Json::U64(map.tileset.count as u64)),
+		("margin", Json::U64(0)),
+		("spacing", Json::U64(0))
+	]);
+
+	let next_layer = layers.len() as u64 + 1;
+
+	Ok(json_object(vec![
+		("type", Json::String("map".to_string())),
+		("orientation", Json::String("staggered".to_string())),
+		("staggeraxis", Json::String("y".to_string())),
+		("staggerindex", Json::String("odd".to_string())),
+		("renderorder", Json::String("right-down".to_string())),
+		("infinite", Json::Boolean(false)),
+		("width", Json::U64(map.width as u64)),
+		("height", Json::U64(map.height as u64)),
+		("tilewidth", Json::U64(tile_width)),
+		("tileheight", Json::U64(tile_height)),
+		("nextlayerid", Json::U64(next_layer)),
+		("nextobjectid", Json::U64(first_portal + map.portals.len() as u64)),
+		("layers", Json::Array(layers)),
+		("tilesets", Json::Array(vec![tileset]))
+	]).pretty().to_string())
+}
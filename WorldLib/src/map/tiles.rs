@@ -0,0 +1,97 @@
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use std::path::Path;
+use rustc_serialize::json;
+use utils::to_io;
+use map::Tile;
+
+/**
+ * Gameplay properties of the tileset tile at (x, y)
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct TileDef {
+	pub x: usize,
+	pub y: usize,
+	pub name: String,
+	pub walkable: bool,
+	pub cost: f64,
+	pub blocks_sight: bool,
+	pub damage: i32
+}
+
+/**
+ * Maps tileset tiles to their TileDef, tiles without an entry are open ground
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct TileRegistry {
+	pub tiles: Vec<TileDef>
+}
+
+impl TileRegistry {
+
+	pub fn new() -> TileRegistry {
+		TileRegistry {
+			tiles: Vec::new()
+		}
+	}
+
+	/**
+	 * Load the definitions kept next to a tileset, e.g. grass.tiles beside grass.info
+	 */
+	pub fn load(path: &Path) -> io::Result<TileRegistry> {
+		let mut data = String::new();
+		try!(try!(File::open(path)).read_to_string(&mut data));
+		TileRegistry::from_json(&data)
+	}
+
+	/**
+	 * Path finding assumes no step costs less than open ground, so tiles
+	 * cheaper than 1 are refused rather than quietly making routes worse
+	 */
+	pub fn from_json(data: &str) -> io::Result<TileRegistry> {
+		let registry: TileRegistry = try!(to_io(json::decode(data)));
+
+		match registry.tiles.iter().find(|def| !(def.cost >= 1.0) || !def.cost.is_finite()) {
+			Some(def) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} costs {}, tiles must cost at least 1", def.name, def.cost))),
+			None => Ok(registry)
+		}
+	}
+
+	pub fn get(&self, tile: Tile) -> Option<&TileDef> {
+		self.tiles.iter().find(|def| def.x == tile.x && def.y == tile.y)
+	}
+
+	pub fn walkable(&self, tile: Tile) -> bool {
+		self.get(tile).map(|def| def.walkable).unwrap_or(true)
+	}
+
+	pub fn cost(&self, tile: Tile) -> f64 {
+		self.get(tile).map(|def| def.cost).unwrap_or(1.0)
+	}
+
+	pub fn blocks_sight(&self, tile: Tile) -> bool {
+		self.get(tile).map(|def| def.blocks_sight).unwrap_or(false)
+	}
+
+	pub fn damage(&self, tile: Tile) -> i32 {
+		self.get(tile).map(|def| def.damage).unwrap_or(0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn registry(cost: &str) -> String {
+		format!("{{\"tiles\":[{{\"x\":0,\"y\":18,\"name\":\"water\",\"walkable\":true,\"cost\":{},\"blocks_sight\":false,\"damage\":0}}]}}", cost)
+	}
+
+	#[test]
+	fn refuses_tiles_cheaper_than_open_ground() {
+		assert_eq!(TileRegistry::from_json(&registry("1.0")).unwrap().cost(Tile { x: 0, y: 18 }), 1.0);
+		assert_eq!(TileRegistry::from_json(&registry("4.5")).unwrap().cost(Tile { x: 0, y: 18 }), 4.5);
+		assert!(TileRegistry::from_json(&registry("0.5")).is_err());
+		assert!(TileRegistry::from_json(&registry("-1")).is_err());
+	}
+}
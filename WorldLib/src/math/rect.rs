@@ -0,0 +1,149 @@
+use math::Vec2;
+
+/**
+ * An axis aligned box, pos is the top left corner
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+	pub pos: Vec2,
+	pub size: Vec2
+}
+
+impl Rect {
+
+	pub fn new(pos: Vec2, size: Vec2) -> Rect {
+		Rect {
+			pos: pos,
+			size: size
+		}
+	}
+
+	pub fn min(&self) -> Vec2 {
+		self.pos
+	}
+
+	pub fn max(&self) -> Vec2 {
+		self.pos + self.size
+	}
+
+	pub fn centre(&self) -> Vec2 {
+		self.pos + self.size / 2.0
+	}
+
+	/**
+	 * The middle of the bottom edge, where something drawn in the box stands
+	 */
+	pub fn bottom_centre(&self) -> Vec2 {
+		self.pos + Vec2::new(self.size.x / 2.0, self.size.y)
+	}
+
+	pub fn translate(&self, offset: Vec2) -> Rect {
+		Rect::new(self.pos + offset, self.size)
+	}
+
+	/**
+	 * Grow the box by amount on every side
+	 */
+	pub fn expand(&self, amount: f64) -> Rect {
+		Rect::new(self.pos - Vec2::new(amount, amount), self.size + Vec2::new(amount * 2.0, amount * 2.0))
+	}
+
+	/**
+	 * Points on the edge count as inside
+	 */
+	pub fn contains(&self, point: Vec2) -> bool {
+		let max = self.max();
+		point.x >= self.pos.x && point.y >= self.pos.y && point.x <= max.x && point.y <= max.y
+	}
+
+	/**
+	 * Is other entirely inside this box
+	 */
+	pub fn encloses(&self, other: &Rect) -> bool {
+		self.contains(other.min()) && self.contains(other.max())
+	}
+
+	/**
+	 * Do the boxes overlap? Boxes that only touch along an edge don't.
+	 */
+	pub fn intersects(&self, other: &Rect) -> bool {
+		let (a, b) = (self.max(), other.max());
+		self.pos.x < b.x && other.pos.x < a.x && self.pos.y < b.y && other.pos.y < a.y
+	}
+
+	/**
+	 * Do the boxes overlap or touch
+	 */
+	pub fn touches(&self, other: &Rect) -> bool {
+		let (a, b) = (self.max(), other.max());
+		self.pos.x <= b.x && other.pos.x <= a.x && self.pos.y <= b.y && other.pos.y <= a.y
+	}
+
+	/**
+	 * The point in the box closest to point
+	 */
+	pub fn clamp(&self, point: Vec2) -> Vec2 {
+		let max = self.max();
+		Vec2::new(point.x.max(self.pos.x).min(max.x), point.y.max(self.pos.y).min(max.y))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use math::Vec2;
+
+	fn rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+		Rect::new(Vec2::new(x, y), Vec2::new(width, height))
+	}
+
+	#[test]
+	fn overlapping_boxes_intersect_and_touch() {
+		let a = rect(0.0, 0.0, 10.0, 10.0);
+
+		for other in [rect(5.0, 5.0, 10.0, 10.0), rect(-5.0, 2.0, 6.0, 1.0), rect(2.0, 2.0, 1.0, 1.0), a].iter() {
+			assert!(a.intersects(other) && other.intersects(&a), "{:?}", other);
+			assert!(a.touches(other) && other.touches(&a), "{:?}", other);
+		}
+	}
+
+	#[test]
+	fn boxes_sharing_an_edge_or_corner_touch_without_intersecting() {
+		let a = rect(0.0, 0.0, 10.0, 10.0);
+
+		for other in [rect(10.0, 0.0, 5.0, 5.0), rect(0.0, 10.0, 5.0, 5.0), rect(-5.0, -5.0, 5.0, 5.0), rect(10.0, 10.0, 1.0, 1.0)].iter() {
+			assert!(!a.intersects(other) && !other.intersects(&a), "{:?}", other);
+			assert!(a.touches(other) && other.touches(&a), "{:?}", other);
+		}
+	}
+
+	#[test]
+	fn separate_boxes_neither_intersect_nor_touch() {
+		let a = rect(0.0, 0.0, 10.0, 10.0);
+
+		for other in [rect(10.5, 0.0, 5.0, 5.0), rect(0.0, -6.0, 5.0, 5.0), rect(20.0, 20.0, 1.0, 1.0)].iter() {
+			assert!(!a.intersects(other) && !a.touches(other), "{:?}", other);
+		}
+	}
+
+	#[test]
+	fn encloses_includes_the_edges() {
+		let a = rect(0.0, 0.0, 10.0, 10.0);
+
+		assert!(a.encloses(&a));
+		assert!(a.encloses(&rect(2.0, 2.0, 3.0, 3.0)));
+		assert!(a.encloses(&rect(0.0, 5.0, 10.0, 5.0)));
+		assert!(!a.encloses(&rect(5.0, 5.0, 5.5, 1.0)));
+		assert!(!a.encloses(&rect(-1.0, 0.0, 2.0, 2.0)));
+		assert!(!rect(2.0, 2.0, 3.0, 3.0).encloses(&a));
+	}
+
+	#[test]
+	fn clamp_finds_the_nearest_point_inside() {
+		let a = rect(0.0, 0.0, 10.0, 10.0);
+
+		assert_eq!(a.clamp(Vec2::new(5.0, 5.0)), Vec2::new(5.0, 5.0));
+		assert_eq!(a.clamp(Vec2::new(-3.0, 20.0)), Vec2::new(0.0, 10.0));
+		assert_eq!(a.expand(2.0), rect(-2.0, -2.0, 14.0, 14.0));
+	}
+}
@@ -0,0 +1,133 @@
+use math::Vec2;
+
+/**
+ * A 2D affine transform stored as the top two rows of a 3x3 matrix, the
+ * same layout as piston's Matrix2d. Chained calls apply to points in reverse
+ * order, so identity().scale(s).translate(t) translates and then scales.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform2d {
+	m: [[f64; 3]; 2]
+}
+
+impl Transform2d {
+
+	pub fn identity() -> Transform2d {
+		Transform2d::from_matrix([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]])
+	}
+
+	pub fn from_matrix(m: [[f64; 3]; 2]) -> Transform2d {
+		Transform2d {
+			m: m
+		}
+	}
+
+	pub fn as_matrix(&self) -> [[f64; 3]; 2] {
+		self.m
+	}
+
+	/**
+	 * self applied after other
+	 */
+	pub fn then(&self, other: &Transform2d) -> Transform2d {
+		let (a, b) = (self.m, other.m);
+		Transform2d::from_matrix([
+			[a[0][0] * b[0][0] + a[0][1] * b[1][0], a[0][0] * b[0][1] + a[0][1] * b[1][1], a[0][0] * b[0][2] + a[0][1] * b[1][2] + a[0][2]],
+			[a[1][0] * b[0][0] + a[1][1] * b[1][0], a[1][0] * b[0][1] + a[1][1] * b[1][1], a[1][0] * b[0][2] + a[1][1] * b[1][2] + a[1][2]]
+		])
+	}
+
+	pub fn translate(&self, offset: Vec2) -> Transform2d {
+		self.then(&Transform2d::from_matrix([[1.0, 0.0, offset.x], [0.0, 1.0, offset.y]]))
+	}
+
+	pub fn scale(&self, scale: Vec2) -> Transform2d {
+		self.then(&Transform2d::from_matrix([[scale.x, 0.0, 0.0], [0.0, scale.y, 0.0]]))
+	}
+
+	pub fn rotate(&self, radians: f64) -> Transform2d {
+		let (sin, cos) = radians.sin_cos();
+		self.then(&Transform2d::from_matrix([[cos, -sin, 0.0], [sin, cos, 0.0]]))
+	}
+
+	pub fn apply(&self, point: Vec2) -> Vec2 {
+		let m = self.m;
+		Vec2::new(m[0][0] * point.x + m[0][1] * point.y + m[0][2], m[1][0] * point.x + m[1][1] * point.y + m[1][2])
+	}
+
+	/**
+	 * The transform undoing this one, None if it collapses space and can't be undone
+	 */
+	pub fn inverse(&self) -> Option<Transform2d> {
+		let m = self.m;
+		let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+
+		if det == 0.0 {
+			return None;
+		}
+
+		let (a, b, c, d) = (m[1][1] / det, -m[0][1] / det, -m[1][0] / det, m[0][0] / det);
+
+		Some(Transform2d::from_matrix([
+			[a, b, -(a * m[0][2] + b * m[1][2])],
+			[c, d, -(c * m[0][2] + d * m[1][2])]
+		]))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::f64::consts::PI;
+	use math::Vec2;
+
+	fn close(a: Vec2, b: Vec2) -> bool {
+		a.distance(b) < 1e-9
+	}
+
+	fn transforms() -> Vec<Transform2d> {
+		vec![
+			Transform2d::identity(),
+			Transform2d::identity().translate(Vec2::new(3.0, -4.0)),
+			Transform2d::identity().scale(Vec2::new(2.0, 0.5)),
+			Transform2d::identity().rotate(PI / 3.0),
+			Transform2d::identity().scale(Vec2::new(2.0, 3.0)).translate(Vec2::new(-7.0, 1.0)).rotate(0.4)
+		]
+	}
+
+	#[test]
+	fn then_applies_the_other_transform_first() {
+		let scale = Transform2d::identity().scale(Vec2::new(2.0, 2.0));
+		let translate = Transform2d::identity().translate(Vec2::new(1.0, 0.0));
+		let point = Vec2::new(1.0, 1.0);
+
+		assert_eq!(scale.then(&translate).apply(point), Vec2::new(4.0, 2.0));
+		assert_eq!(translate.then(&scale).apply(point), Vec2::new(3.0, 2.0));
+		assert_eq!(scale.translate(Vec2::new(1.0, 0.0)), scale.then(&translate));
+
+		for a in transforms() {
+			for b in transforms() {
+				assert!(close(a.then(&b).apply(point), a.apply(b.apply(point))));
+			}
+		}
+	}
+
+	#[test]
+	fn inverse_undoes_the_transform() {
+		let points = [Vec2::zero(), Vec2::new(1.0, 0.0), Vec2::new(-3.5, 12.0)];
+
+		for transform in transforms() {
+			let inverse = transform.inverse().expect("an inverse");
+
+			for &point in points.iter() {
+				assert!(close(inverse.apply(transform.apply(point)), point), "{:?} at {:?}", transform, point);
+				assert!(close(transform.apply(inverse.apply(point)), point), "{:?} at {:?}", transform, point);
+			}
+		}
+	}
+
+	#[test]
+	fn collapsing_transforms_have_no_inverse() {
+		assert_eq!(Transform2d::identity().scale(Vec2::new(0.0, 1.0)).inverse(), None);
+	}
+}
@@ -0,0 +1,201 @@
+use std::ops::{Add, Sub, Mul, Div, Neg, AddAssign, SubAssign};
+use rustc_serialize::{Encodable, Encoder, Decodable, Decoder};
+
+/**
+ * A point or direction in world units
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Vec2 {
+	pub x: f64,
+	pub y: f64
+}
+
+impl Vec2 {
+
+	pub fn new(x: f64, y: f64) -> Vec2 {
+		Vec2 {
+			x: x,
+			y: y
+		}
+	}
+
+	pub fn zero() -> Vec2 {
+		Vec2::new(0.0, 0.0)
+	}
+
+	pub fn is_zero(&self) -> bool {
+		self.x == 0.0 && self.y == 0.0
+	}
+
+	pub fn dot(&self, other: Vec2) -> f64 {
+		self.x * other.x + self.y * other.y
+	}
+
+	pub fn length_squared(&self) -> f64 {
+		self.dot(*self)
+	}
+
+	pub fn length(&self) -> f64 {
+		self.length_squared().sqrt()
+	}
+
+	/**
+	 * The same direction with a length of one, the zero vector stays zero
+	 */
+	pub fn normalize(&self) -> Vec2 {
+		let length = self.length();
+
+		if length == 0.0 {
+			Vec2::zero()
+		} else {
+			*self / length
+		}
+	}
+
+	pub fn distance(&self, other: Vec2) -> f64 {
+		(other - *self).length()
+	}
+
+	/**
+	 * Linear interpolation, t of 0 is self and 1 is other
+	 */
+	pub fn lerp(&self, other: Vec2, t: f64) -> Vec2 {
+		*self + (other - *self) * t
+	}
+
+	pub fn is_finite(&self) -> bool {
+		self.x.is_finite() && self.y.is_finite()
+	}
+}
+
+impl From<(f64, f64)> for Vec2 {
+	fn from((x, y): (f64, f64)) -> Vec2 {
+		Vec2::new(x, y)
+	}
+}
+
+impl Add for Vec2 {
+	type Output = Vec2;
+
+	fn add(self, other: Vec2) -> Vec2 {
+		Vec2::new(self.x + other.x, self.y + other.y)
+	}
+}
+
+impl Sub for Vec2 {
+	type Output = Vec2;
+
+	fn sub(self, other: Vec2) -> Vec2 {
+		Vec2::new(self.x - other.x, self.y - other.y)
+	}
+}
+
+impl Mul<f64> for Vec2 {
+	type Output = Vec2;
+
+	fn mul(self, scalar: f64) -> Vec2 {
+		Vec2::new(self.x * scalar, self.y * scalar)
+	}
+}
+
+impl Div<f64> for Vec2 {
+	type Output = Vec2;
+
+	fn div(self, scalar: f64) -> Vec2 {
+		Vec2::new(self.x / scalar, self.y / scalar)
+	}
+}
+
+impl Neg for Vec2 {
+	type Output = Vec2;
+
+	fn neg(self) -> Vec2 {
+		Vec2::new(-self.x, -self.y)
+	}
+}
+
+impl AddAssign for Vec2 {
+	fn add_assign(&mut self, other: Vec2) {
+		*self = *self + other;
+	}
+}
+
+impl SubAssign for Vec2 {
+	fn sub_assign(&mut self, other: Vec2) {
+		*self = *self - other;
+	}
+}
+
+/**
+ * Encoded as an (x, y) tuple, the same as the old Vec2d, so saved characters
+ * and older clients still read it
+ */
+impl Encodable for Vec2 {
+	fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+		s.emit_tuple(2, |s| {
+			try!(s.emit_tuple_arg(0, |s| self.x.encode(s)));
+			s.emit_tuple_arg(1, |s| self.y.encode(s))
+		})
+	}
+}
+
+impl Decodable for Vec2 {
+	fn decode<D: Decoder>(d: &mut D) -> Result<Vec2, D::Error> {
+		d.read_tuple(2, |d| {
+			let x = try!(d.read_tuple_arg(0, Decodable::decode));
+			let y = try!(d.read_tuple_arg(1, Decodable::decode));
+			Ok(Vec2::new(x, y))
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn operators() {
+		let (a, b) = (Vec2::new(1.0, 2.0), Vec2::new(3.0, -5.0));
+
+		assert_eq!(a + b, Vec2::new(4.0, -3.0));
+		assert_eq!(a - b, Vec2::new(-2.0, 7.0));
+		assert_eq!(a * 3.0, Vec2::new(3.0, 6.0));
+		assert_eq!(b / 2.0, Vec2::new(1.5, -2.5));
+		assert_eq!(-a, Vec2::new(-1.0, -2.0));
+		assert_eq!(a.dot(b), -7.0);
+
+		let mut c = a;
+		c += b;
+		assert_eq!(c, a + b);
+		c -= b;
+		assert_eq!(c, a);
+	}
+
+	#[test]
+	fn lengths() {
+		let v = Vec2::new(3.0, 4.0);
+
+		assert_eq!(v.length(), 5.0);
+		assert_eq!(v.length_squared(), 25.0);
+		assert_eq!(v.distance(Vec2::zero()), 5.0);
+		assert_eq!(v.normalize(), Vec2::new(0.6, 0.8));
+	}
+
+	#[test]
+	fn normalizing_zero_stays_zero() {
+		let zero = Vec2::zero().normalize();
+
+		assert!(zero.is_zero());
+		assert!(zero.is_finite());
+	}
+
+	#[test]
+	fn lerp_runs_from_self_to_other() {
+		let (a, b) = (Vec2::new(0.0, 10.0), Vec2::new(4.0, -10.0));
+
+		assert_eq!(a.lerp(b, 0.0), a);
+		assert_eq!(a.lerp(b, 1.0), b);
+		assert_eq!(a.lerp(b, 0.25), Vec2::new(1.0, 5.0));
+		assert_eq!(a.lerp(b, 2.0), Vec2::new(8.0, -30.0));
+	}
+}
@@ -0,0 +1,307 @@
+use std::io;
+use std::io::{Error, ErrorKind};
+use bincode::SizeLimit;
+use bincode::rustc_serialize::{encode as encode_body, decode as decode_body};
+use utils::to_io;
+use message::Message;
+
+/**
+ * Frames are laid out as
+ *
+ *   version: u8 | tag: u8 | length: u32 (big endian) | body: [u8; length]
+ *
+ * where the body is the bincode encoding of the Message and tag identifies its variant
+ */
+pub const FRAME_VERSION: u8 = 1;
+pub const HEADER_SIZE: usize = 6;
+pub const MAX_BODY_SIZE: usize = 1 << 24;
+
+fn invalid(reason: &str) -> Error {
+	Error::new(ErrorKind::InvalidData, reason)
+}
+
+pub fn encode(message: &Message) -> io::Result<Vec<u8>> {
+	let body = try!(to_io(encode_body(message, SizeLimit::Bounded(MAX_BODY_SIZE as u64))));
+	let len = body.len();
+
+	let mut frame = Vec::with_capacity(HEADER_SIZE + len);
+	frame.push(FRAME_VERSION);
+	frame.push(message.tag());
+	frame.push((len >> 24) as u8);
+	frame.push((len >> 16) as u8);
+	frame.push((len >> 8) as u8);
+	frame.push(len as u8);
+	frame.extend_from_slice(&body);
+
+	Ok(frame)
+}
+
+/**
+ * Read the header at the front of buf, returning the tag and body length
+ * or None if the header has not fully arrived yet
+ */
+fn header(buf: &[u8]) -> io::Result<Option<(u8, usize)>> {
+	if buf.len() < HEADER_SIZE {
+		return Ok(None);
+	}
+
+	if buf[0] != FRAME_VERSION {
+		return Err(invalid("Unsupported frame version"));
+	}
+
+	let len = ((buf[2] as usize) << 24) | ((buf[3] as usize) << 16) | ((buf[4] as usize) << 8) | (buf[5] as usize);
+
+	if len > MAX_BODY_SIZE {
+		return Err(invalid("Frame exceeds the maximum body size"));
+	}
+
+	Ok(Some((buf[1], len)))
+}
+
+/**
+ * Decode the frame at the front of buf. Returns the message and the number of
+ * bytes it used, or None if the frame is incomplete.
+ */
+pub fn decode(buf: &[u8]) -> io::Result<Option<(Message, usize)>> {
+	let (tag, len) = match try!(header(buf)) {
+		Some(header) => header,
+		None => return Ok(None)
+	};
+
+	if buf.len() < HEADER_SIZE + len {
+		return Ok(None);
+	}
+
+	let message: Message = try!(to_io(decode_body(&buf[HEADER_SIZE..HEADER_SIZE + len])));
+
+	if message.tag() != tag {
+		return Err(invalid("Frame tag does not match its body"));
+	}
+
+	Ok(Some((message, HEADER_SIZE + len)))
+}
+
+/**
+ * Pop the next complete message off the front of buf.
+ *
+ * A frame with a valid header but a bad body is dropped from the buffer before
+ * the error is returned so the stream stays in sync. A bad header leaves the
+ * buffer untouched as there is no way to find the next frame.
+ */
+pub fn next(buf: &mut Vec<u8>) -> io::Result<Option<Message>> {
+	match decode(buf) {
+		Ok(Some((message, used))) => {
+			buf.drain(..used);
+			Ok(Some(message))
+		},
+		Ok(None) => Ok(None),
+		Err(e) => {
+			if let Ok(Some((_, len))) = header(buf) {
+				buf.drain(..HEADER_SIZE + len);
+			}
+			Err(e)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use message::{Message, Role};
+	use message::protocol::PROTOCOL_VERSION;
+	use entity::{Entity, EntityID, ComponentData, ComponentKind, Position, Health};
+	use map::{Map, Tile, TileEdit};
+	use world::World;
+	use math::Vec2;
+
+	fn id() -> EntityID {
+		EntityID { index: 3, generation: 1 }
+	}
+
+	fn edit() -> TileEdit {
+		TileEdit { layer: 0, x: 1, y: 2, tile: Tile { x: 4, y: 5 } }
+	}
+
+	fn every_message() -> Vec<Message> {
+		let mut world = World::new(Map::new(4, 6));
+		world.spawn(vec![ComponentData::Position(Position { pos: Vec2::new(10.0, 20.0) })]);
+
+		let entity = Entity {
+			id: id(),
+			components: vec![ComponentData::Health(Health::new(10))]
+		};
+
+		vec![
+			Message::Hello(PROTOCOL_VERSION, 0),
+			Message::Welcome(PROTOCOL_VERSION, 0),
+			Message::Login("user".to_string(), "password".to_string()),
+			Message::Register("user".to_string(), "password".to_string()),
+			Message::Say("hello".to_string()),
+			Message::Kill("bye".to_string()),
+			Message::Map(Map::new(3, 5)),
+			Message::World(world),
+			Message::Entity(entity.clone()),
+			Message::RemoveEntity(id()),
+			Message::Move(Vec2::new(1.5, -2.5)),
+			Message::Entities(vec![entity]),
+			Message::Error("nope".to_string()),
+			Message::SetTile(edit()),
+			Message::SetTiles(vec![edit(), edit()]),
+			Message::Components(vec![(id(), ComponentData::Position(Position { pos: Vec2::zero() }))]),
+			Message::RemoveComponent(id(), ComponentKind::Velocity),
+			Message::MoveTo(7, 8),
+			Message::Control(id()),
+			Message::Role(Role::Builder)
+		]
+	}
+
+	#[test]
+	fn round_trips_every_message() {
+		let messages = every_message();
+		let mut tags: Vec<u8> = messages.iter().map(|message| message.tag()).collect();
+		tags.sort();
+		tags.dedup();
+		assert_eq!(tags.len(), messages.len());
+
+		for message in messages {
+			let frame = encode(&message).unwrap();
+			let (decoded, used) = decode(&frame).unwrap().unwrap();
+
+			assert_eq!(used, frame.len());
+			assert_eq!(decoded.tag(), message.tag());
+			assert_eq!(encode(&decoded).unwrap(), frame);
+		}
+	}
+
+	#[test]
+	fn waits_for_the_whole_frame() {
+		for message in every_message() {
+			let frame = encode(&message).unwrap();
+			let mut buf = Vec::new();
+
+			for (i, byte) in frame.iter().enumerate() {
+				buf.push(*byte);
+
+				if i + 1 < frame.len() {
+					assert!(next(&mut buf).unwrap().is_none());
+					assert_eq!(buf.len(), i + 1);
+				}
+			}
+
+			let decoded = next(&mut buf).unwrap().unwrap();
+			assert_eq!(encode(&decoded).unwrap(), frame);
+			assert!(buf.is_empty());
+		}
+	}
+
+	#[test]
+	fn splits_frames_sharing_a_buffer() {
+		let mut buf = encode(&Message::Say("first".to_string())).unwrap();
+		buf.extend(encode(&Message::MoveTo(1, 2)).unwrap());
+
+		match next(&mut buf).unwrap() {
+			Some(Message::Say(ref text)) if text == "first" => {},
+			other => panic!("expected the first frame, got {:?}", other)
+		}
+
+		match next(&mut buf).unwrap() {
+			Some(Message::MoveTo(1, 2)) => {},
+			other => panic!("expected the second frame, got {:?}", other)
+		}
+
+		assert!(next(&mut buf).unwrap().is_none());
+		assert!(buf.is_empty());
+	}
+
+	#[test]
+	fn refuses_other_frame_versions() {
+		let mut buf = encode(&Message::Say("hi".to_string())).unwrap();
+		buf[0] = FRAME_VERSION + 1;
+		let len = buf.len();
+
+		assert!(next(&mut buf).is_err());
+		assert_eq!(buf.len(), len);
+	}
+
+	#[test]
+	fn drops_a_frame_whose_tag_does_not_match() {
+		let mut buf = encode(&Message::Say("hi".to_string())).unwrap();
+		buf[1] = Message::Kill(String::new()).tag();
+		let after = encode(&Message::MoveTo(3, 4)).unwrap();
+		buf.extend(after.iter().cloned());
+
+		assert!(next(&mut buf).is_err());
+		assert_eq!(buf, after);
+
+		match next(&mut buf).unwrap() {
+			Some(Message::MoveTo(3, 4)) => {},
+			other => panic!("expected the following frame, got {:?}", other)
+		}
+	}
+
+	#[test]
+	fn refuses_oversized_frames() {
+		let len = MAX_BODY_SIZE + 1;
+		let mut buf = vec![FRAME_VERSION, Message::Say(String::new()).tag(), (len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+
+		assert!(decode(&buf).is_err());
+		assert!(next(&mut buf).is_err());
+		assert_eq!(buf.len(), HEADER_SIZE);
+	}
+
+	/**
+	 * A frame around any body, with the header describing it
+	 */
+	fn frame(tag: u8, body: &[u8]) -> Vec<u8> {
+		let len = body.len();
+		let mut buf = vec![FRAME_VERSION, tag, (len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+		buf.extend_from_slice(body);
+		buf
+	}
+
+	#[test]
+	fn refuses_truncated_bodies() {
+		for message in every_message() {
+			let whole = encode(&message).unwrap();
+			let body = &whole[HEADER_SIZE..];
+
+			for &cut in [0, body.len() / 2, body.len() - 1].iter() {
+				let mut buf = frame(message.tag(), &body[..cut]);
+				assert!(decode(&buf).is_err(), "{:?} cut to {} bytes", message, cut);
+				assert!(next(&mut buf).is_err());
+				assert!(buf.is_empty());
+			}
+		}
+	}
+
+	#[test]
+	fn refuses_garbage_bodies() {
+		let say = encode(&Message::Say("hi".to_string())).unwrap();
+
+		//A real variant claiming a string far longer than the frame
+		let mut huge = say[HEADER_SIZE..HEADER_SIZE + 4].to_vec();
+		huge.extend_from_slice(&[0xFF; 8]);
+
+		let bodies = vec![vec![0xFF; 32], vec![0x00; 3], huge];
+
+		for body in bodies {
+			let mut buf = frame(say[1], &body);
+			assert!(decode(&buf).is_err(), "{:?}", body);
+			assert!(next(&mut buf).is_err());
+			assert!(buf.is_empty());
+		}
+	}
+
+	#[test]
+	fn refuses_unknown_tags() {
+		let known: Vec<u8> = every_message().iter().map(|message| message.tag()).collect();
+		let unknown = (0..255).find(|tag| !known.contains(tag)).unwrap();
+
+		let mut buf = encode(&Message::Say("hi".to_string())).unwrap();
+		buf[1] = unknown;
+
+		assert!(decode(&buf).is_err());
+		assert!(next(&mut buf).is_err());
+		assert!(buf.is_empty());
+	}
+}
@@ -1,22 +1,100 @@
 use rustc_serialize::json;
-use std::io;
 use std::result::Result;
-use std::str::from_utf8;
-use utils::to_io;
-use entity::EntityID;
+use entity::{Entity, EntityID, ComponentData, ComponentKind};
+use world::World;
+use map::{Map, TileEdit};
+use math::Vec2;
+
+mod frame;
+mod role;
+pub mod protocol;
+
+pub use self::frame::{encode, decode, next, FRAME_VERSION, HEADER_SIZE, MAX_BODY_SIZE};
+pub use self::protocol::Capabilities;
+pub use self::role::Role;
 
 #[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
 pub enum Message {
+	Hello(u32, Capabilities),
+	Welcome(u32, Capabilities),
 	Login(String, String),
+	Register(String, String),
 	Say(String),
 	Kill(String),
-	Map(String),
-	World(String),
-	Entity(String),
+	Map(Map),
+	World(World),
+	Entity(Entity),
 	RemoveEntity(EntityID),
+	Move(Vec2),
+	Entities(Vec<Entity>),
+	Error(String),
+	SetTile(TileEdit),
+	SetTiles(Vec<TileEdit>),
+	Components(Vec<(EntityID, ComponentData)>),
+	RemoveComponent(EntityID, ComponentKind),
+	MoveTo(usize, usize),
+	Control(EntityID),
+	Role(Role),
 }
 
 impl Message {
+
+	/**
+	 * Identifies the variant in the frame header
+	 */
+	pub fn tag(&self) -> u8 {
+		match *self {
+			Message::Login(..) => 1,
+			Message::Register(..) => 2,
+			Message::Say(..) => 3,
+			Message::Kill(..) => 4,
+			Message::Map(..) => 5,
+			Message::World(..) => 6,
+			Message::Entity(..) => 7,
+			Message::RemoveEntity(..) => 8,
+			Message::Hello(..) => 9,
+			Message::Welcome(..) => 10,
+			Message::Move(..) => 11,
+			Message::Entities(..) => 12,
+			Message::Error(..) => 13,
+			Message::SetTile(..) => 14,
+			Message::SetTiles(..) => 15,
+			Message::Components(..) => 16,
+			Message::RemoveComponent(..) => 17,
+			Message::MoveTo(..) => 18,
+			Message::Control(..) => 19,
+			Message::Role(..) => 20
+		}
+	}
+
+	/**
+	 * A short human readable name for logs and error replies
+	 */
+	pub fn name(&self) -> &'static str {
+		match *self {
+			Message::Hello(..) => "hello",
+			Message::Welcome(..) => "welcome",
+			Message::Login(..) => "login",
+			Message::Register(..) => "register",
+			Message::Say(..) => "say",
+			Message::Kill(..) => "kill",
+			Message::Map(..) => "replace the map",
+			Message::World(..) => "replace the world",
+			Message::Entity(..) => "update an entity",
+			Message::RemoveEntity(..) => "remove an entity",
+			Message::Move(..) => "move",
+			Message::Entities(..) => "update entities",
+			Message::Error(..) => "error",
+			Message::SetTile(..) => "edit a tile",
+			Message::SetTiles(..) => "edit tiles",
+			Message::Components(..) => "update components",
+			Message::RemoveComponent(..) => "remove a component",
+			Message::MoveTo(..) => "move to a tile",
+			Message::Control(..) => "take control of an entity",
+			Message::Role(..) => "set a role"
+		}
+	}
+
 	pub fn as_json(&self) -> String {
 		use rustc_serialize::json;
 		json::encode(&self).unwrap()
@@ -26,26 +104,3 @@ impl Message {
 		json::decode(msg)
 	}
 }
-
-pub fn next(buf: &Vec<u8>) -> io::Result<Option<(Message, Vec<u8>)>> {
-
-	//Find the null terminator that splits messages
-    match buf.iter().position(|&x| x == 0) {
-    	Some(idx) => {
-
-	        //Split remain_p to include the null terminator
-	        let (msg_p, next_p) = buf.split_at(idx);
-			let r_remain = &next_p[1..];
-
-			let text = try!(to_io(from_utf8(msg_p))).trim();
-
-			if text.len() != 0 {
-				let new_msg = try!(to_io(Message::from_json(text.trim())));
-				Ok(Some((new_msg, r_remain.to_vec())))
-			} else {
-				Ok(None)
-			}
-	   	},
-	   	_ => Ok(None)
-	}
-}
\ No newline at end of file
@@ -0,0 +1,33 @@
+/**
+ * Bumped whenever Message, Entity, World or Map change in a way old peers cannot read
+ */
+pub const PROTOCOL_VERSION: u32 = 7;
+
+/**
+ * The oldest protocol version this build can still talk to
+ */
+pub const MIN_PROTOCOL_VERSION: u32 = 7;
+
+/**
+ * Optional protocol features, negotiated as a bit set during the handshake
+ */
+pub type Capabilities = u32;
+
+pub const CAP_COMPRESSION: Capabilities = 1 << 0;
+pub const CAP_DELTA_UPDATES: Capabilities = 1 << 1;
+
+/**
+ * The capabilities this build implements
+ */
+pub const SUPPORTED_CAPABILITIES: Capabilities = 0;
+
+pub fn compatible(version: u32) -> bool {
+	version >= MIN_PROTOCOL_VERSION && version <= PROTOCOL_VERSION
+}
+
+/**
+ * The capabilities both sides support
+ */
+pub fn negotiate(offered: Capabilities) -> Capabilities {
+	offered & SUPPORTED_CAPABILITIES
+}
@@ -0,0 +1,82 @@
+use message::Message;
+
+/**
+ * What a user is allowed to do, ordered from least to most privileged
+ */
+#[derive(RustcEncodable, RustcDecodable, Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Role {
+	Player,
+	Builder,
+	Admin
+}
+
+impl Role {
+
+	/**
+	 * The least privileged role that may send this message once logged in,
+	 * None for messages a client should never send after the handshake
+	 */
+	pub fn required(message: &Message) -> Option<Role> {
+		match *message {
+			Message::Say(..) | Message::Move(..) | Message::MoveTo(..) => Some(Role::Player),
+			Message::Map(..) | Message::SetTile(..) | Message::SetTiles(..) => Some(Role::Builder),
+			_ => None
+		}
+	}
+
+	pub fn can(&self, message: &Message) -> bool {
+		match Role::required(message) {
+			Some(role) => *self >= role,
+			None => false
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use message::Message;
+	use map::{Map, TileEdit, Tile};
+	use math::Vec2;
+
+	fn edit() -> TileEdit {
+		TileEdit { layer: 0, x: 0, y: 0, tile: Tile { x: 1, y: 1 } }
+	}
+
+	fn playing() -> Vec<Message> {
+		vec![Message::Say("hi".to_string()), Message::Move(Vec2::new(1.0, 0.0)), Message::MoveTo(2, 3)]
+	}
+
+	fn building() -> Vec<Message> {
+		vec![Message::Map(Map::new(2, 2)), Message::SetTile(edit()), Message::SetTiles(vec![edit()])]
+	}
+
+	fn never() -> Vec<Message> {
+		vec![Message::Login("alice".to_string(), "hunter2".to_string()), Message::Kill("bye".to_string()), Message::Error("no".to_string())]
+	}
+
+	#[test]
+	fn players_can_only_play() {
+		assert!(playing().iter().all(|message| Role::Player.can(message)));
+		assert!(!building().iter().any(|message| Role::Player.can(message)));
+	}
+
+	#[test]
+	fn builders_can_also_build() {
+		assert!(playing().iter().all(|message| Role::Builder.can(message)));
+		assert!(building().iter().all(|message| Role::Builder.can(message)));
+	}
+
+	#[test]
+	fn admins_can_do_anything_builders_can() {
+		assert!(playing().iter().all(|message| Role::Admin.can(message)));
+		assert!(building().iter().all(|message| Role::Admin.can(message)));
+	}
+
+	#[test]
+	fn nobody_can_send_handshake_or_server_messages() {
+		for role in [Role::Player, Role::Builder, Role::Admin].iter() {
+			assert!(!never().iter().any(|message| role.can(message)), "{:?}", role);
+		}
+	}
+}
@@ -0,0 +1,331 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::f64::consts::SQRT_2;
+use map::Map;
+
+/**
+ * A tile coordinate within a Map
+ */
+pub type TilePos = (usize, usize);
+
+/**
+ * The most tiles find will expand before giving up. Routes worth clicking are
+ * far shorter, this stops a goal that can't be reached searching the whole map.
+ */
+pub const SEARCH_LIMIT: usize = 10000;
+
+/**
+ * An entry in the open set, ordered so the BinaryHeap pops the cheapest first
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Open {
+	estimate: f64,
+	pos: TilePos
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+	fn partial_cmp(&self, other: &Open) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Open {
+	fn cmp(&self, other: &Open) -> Ordering {
+		other.estimate.partial_cmp(&self.estimate).unwrap_or(Ordering::Equal)
+	}
+}
+
+fn offset(map: &Map, (x, y): TilePos, d_x: isize, d_y: isize) -> Option<TilePos> {
+	let (x, y) = (x as isize + d_x, y as isize + d_y);
+
+	if x < 0 || y < 0 || !map.contains(x as usize, y as usize) {
+		None
+	} else {
+		Some((x as usize, y as usize))
+	}
+}
+
+/**
+ * The tiles reachable in one step from pos and the distance to each.
+ *
+ * Odd rows are drawn half a tile to the right, so the four tiles sharing an
+ * edge with pos are in the rows above and below and which columns they are
+ * in depends on the rows parity. The four tiles touching a corner (left,
+ * right, two rows up and two rows down) are a diagonal step and can only be
+ * taken when both tiles either side of that corner are walkable.
+ */
+pub fn neighbours(map: &Map, pos: TilePos) -> Vec<(TilePos, f64)> {
+	let (left, right) = if pos.1 % 2 == 0 { (-1, 0) } else { (0, 1) };
+
+	let up_left = offset(map, pos, left, -1);
+	let up_right = offset(map, pos, right, -1);
+	let down_left = offset(map, pos, left, 1);
+	let down_right = offset(map, pos, right, 1);
+
+	let walkable = |tile: Option<TilePos>| tile.map(|(x, y)| map.walkable(x, y)).unwrap_or(false);
+
+	let mut found = Vec::new();
+
+	for &tile in [up_left, up_right, down_left, down_right].iter() {
+		if let Some(tile) = tile {
+			found.push((tile, 1.0));
+		}
+	}
+
+	let corners = [
+		(offset(map, pos, 0, -2), up_left, up_right),
+		(offset(map, pos, 0, 2), down_left, down_right),
+		(offset(map, pos, -1, 0), up_left, down_left),
+		(offset(map, pos, 1, 0), up_right, down_right)
+	];
+
+	for &(tile, a, b) in corners.iter() {
+		if let Some(tile) = tile {
+			if walkable(a) && walkable(b) {
+				found.push((tile, SQRT_2));
+			}
+		}
+	}
+
+	found
+}
+
+/**
+ * Octile distance on the diamond grid, never more than the cheapest possible route
+ */
+pub fn distance(from: TilePos, to: TilePos) -> f64 {
+	let (u_0, v_0) = Map::to_diamond(from);
+	let (u_1, v_1) = Map::to_diamond(to);
+	let d_u = (u_1 - u_0).abs() as f64;
+	let d_v = (v_1 - v_0).abs() as f64;
+	d_u.max(d_v) + (SQRT_2 - 1.0) * d_u.min(d_v)
+}
+
+/**
+ * Find the cheapest route from start to goal with A*. Each step costs its
+ * distance times the movement cost of the tile entered. The route includes
+ * both ends, None if the goal can't be reached within SEARCH_LIMIT tiles.
+ */
+pub fn find(map: &Map, start: TilePos, goal: TilePos) -> Option<Vec<TilePos>> {
+	find_within(map, start, goal, SEARCH_LIMIT)
+}
+
+/**
+ * find, giving up once limit tiles have been expanded. Only the tiles
+ * searched are stored so the cost doesn't grow with the size of the map.
+ */
+pub fn find_within(map: &Map, start: TilePos, goal: TilePos, limit: usize) -> Option<Vec<TilePos>> {
+	if !map.contains(start.0, start.1) || !map.walkable(goal.0, goal.1) {
+		return None;
+	}
+
+	let mut cost: HashMap<TilePos, f64> = HashMap::new();
+	let mut came_from: HashMap<TilePos, TilePos> = HashMap::new();
+	let mut closed: HashSet<TilePos> = HashSet::new();
+	let mut open = BinaryHeap::new();
+
+	cost.insert(start, 0.0);
+	open.push(Open { estimate: distance(start, goal), pos: start });
+
+	while let Some(Open { pos, .. }) = open.pop() {
+		if pos == goal {
+			return Some(route(&came_from, goal));
+		}
+
+		if !closed.insert(pos) {
+			continue;
+		}
+
+		if closed.len() > limit {
+			return None;
+		}
+
+		let here = cost[&pos];
+
+		for (next, step) in neighbours(map, pos) {
+			let next_cost = match map.movement_cost(next.0, next.1) {
+				Some(tile_cost) => here + step * tile_cost,
+				None => continue
+			};
+
+			if next_cost < cost.get(&next).cloned().unwrap_or(::std::f64::INFINITY) {
+				cost.insert(next, next_cost);
+				came_from.insert(next, pos);
+				open.push(Open { estimate: next_cost + distance(next, goal), pos: next });
+			}
+		}
+	}
+
+	None
+}
+
+fn route(came_from: &HashMap<TilePos, TilePos>, goal: TilePos) -> Vec<TilePos> {
+	let mut path = vec![goal];
+	let mut current = goal;
+
+	while let Some(&previous) = came_from.get(&current) {
+		path.push(previous);
+		current = previous;
+	}
+
+	path.reverse();
+	path
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use map::{Map, Tile, TileDef, TileRegistry};
+
+	const WALL: Tile = Tile { x: 1, y: 0 };
+	const MUD: Tile = Tile { x: 2, y: 0 };
+
+	fn def(tile: Tile, name: &str, walkable: bool, cost: f64) -> TileDef {
+		TileDef {
+			x: tile.x,
+			y: tile.y,
+			name: name.to_string(),
+			walkable: walkable,
+			cost: cost,
+			blocks_sight: false,
+			damage: 0
+		}
+	}
+
+	fn map() -> Map {
+		let mut map = Map::new(12, 24);
+		map.registry = TileRegistry {
+			tiles: vec![def(WALL, "wall", false, 1.0), def(MUD, "mud", true, 10.0)]
+		};
+		map
+	}
+
+	fn paint(map: &mut Map, tiles: &[TilePos], tile: Tile) {
+		for &(x, y) in tiles {
+			let idx = map.idx(x, y);
+			map.layers[0][idx] = Some(tile);
+		}
+	}
+
+	/**
+	 * Every step of a route goes to one of the tiles neighbours allows
+	 */
+	fn check_steps(map: &Map, route: &[TilePos]) {
+		for pair in route.windows(2) {
+			assert!(neighbours(map, pair[0]).iter().any(|&(tile, _)| tile == pair[1]), "{:?} to {:?}", pair[0], pair[1]);
+			assert!(map.walkable(pair[1].0, pair[1].1));
+		}
+	}
+
+	fn length(map: &Map, route: &[TilePos]) -> f64 {
+		route.windows(2).map(|pair| {
+			neighbours(map, pair[0]).into_iter().find(|&(tile, _)| tile == pair[1]).map(|(_, step)| step).unwrap()
+		}).sum()
+	}
+
+	fn diamond_line(map: &Map, start: TilePos, steps: isize) -> Vec<TilePos> {
+		let (u, v) = Map::to_diamond(start);
+		(0..steps + 1).map(|i| map.from_diamond((u + i, v)).unwrap()).collect()
+	}
+
+	#[test]
+	fn walks_straight_along_an_edge() {
+		let map = map();
+		let line = diamond_line(&map, (3, 4), 5);
+		let goal = *line.last().unwrap();
+
+		assert_eq!(find(&map, (3, 4), goal), Some(line));
+		assert_eq!(find(&map, (3, 4), (3, 4)), Some(vec![(3, 4)]));
+	}
+
+	#[test]
+	fn goes_around_a_wall() {
+		let mut map = map();
+		let line = diamond_line(&map, (3, 4), 6);
+		let (start, goal) = (line[0], line[6]);
+
+		//A wall three tiles long across the straight line, at its middle
+		let (u, v) = Map::to_diamond(line[3]);
+		let wall: Vec<TilePos> = (-1..2).map(|i| map.from_diamond((u, v + i)).unwrap()).collect();
+		paint(&mut map, &wall, WALL);
+
+		let route = find(&map, start, goal).expect("a way around");
+
+		assert_eq!((route[0], *route.last().unwrap()), (start, goal));
+		assert!(route.iter().all(|tile| !wall.contains(tile)));
+		check_steps(&map, &route);
+		assert!(length(&map, &route) > length(&map, &line));
+	}
+
+	#[test]
+	fn refuses_to_cut_corners() {
+		let mut map = map();
+		let start = (4, 6);
+		let goal = (5, 6);
+
+		//Open ground either side of the corner allows the diagonal step
+		assert_eq!(find(&map, start, goal), Some(vec![start, goal]));
+
+		//Rows above and below an even row are offset to the left
+		let up_right = (4, 5);
+		let down_right = (4, 7);
+		paint(&mut map, &[up_right], WALL);
+
+		assert_eq!(find(&map, start, goal), Some(vec![start, down_right, goal]));
+		assert!(neighbours(&map, start).iter().all(|&(tile, _)| tile != goal));
+	}
+
+	#[test]
+	fn prefers_a_cheaper_detour() {
+		let mut map = map();
+		let line = diamond_line(&map, (3, 4), 6);
+		let mud: Vec<TilePos> = line[1..6].to_vec();
+		paint(&mut map, &mud, MUD);
+
+		let route = find(&map, line[0], line[6]).expect("a route");
+
+		assert!(route.iter().all(|tile| !mud.contains(tile)), "walked through mud, {:?}", route);
+		check_steps(&map, &route);
+	}
+
+	#[test]
+	fn unreachable_goals_have_no_route() {
+		let mut map = map();
+		let goal = (6, 12);
+
+		let around: Vec<TilePos> = neighbours(&map, goal).into_iter().map(|(tile, _)| tile).collect();
+		assert_eq!(around.len(), 8);
+		paint(&mut map, &around, WALL);
+
+		assert_eq!(find(&map, (1, 1), goal), None);
+
+		paint(&mut map, &[(2, 2)], WALL);
+		assert_eq!(find(&map, (1, 1), (2, 2)), None);
+		assert_eq!(find(&map, (1, 1), (100, 100)), None);
+	}
+
+	#[test]
+	fn gives_up_past_the_search_limit() {
+		let map = map();
+		let line = diamond_line(&map, (3, 4), 6);
+
+		assert_eq!(find_within(&map, line[0], line[6], 3), None);
+		assert_eq!(find(&map, line[0], line[6]), Some(line));
+	}
+
+	#[test]
+	fn unreachable_goals_on_large_maps_give_up() {
+		let mut map = map();
+		map.width = 1024;
+		map.height = 1024;
+		map.layers = vec![vec![None; 1024 * 1024]];
+
+		let goal = (512, 512);
+		let around: Vec<TilePos> = neighbours(&map, goal).into_iter().map(|(tile, _)| tile).collect();
+		paint(&mut map, &around, WALL);
+
+		assert_eq!(find(&map, (1, 1), goal), None);
+	}
+}
@@ -0,0 +1,173 @@
+use std::u32;
+use std::collections::HashSet;
+use rustc_serialize::json::{Json, Object};
+use schema::{Kind, SchemaError};
+use utils::json_object;
+
+/**
+ * A World's component stores, in the order Components declares them
+ */
+const STORES: [&'static str; 8] = ["types", "positions", "velocities", "bodies", "sprites", "healths", "inventories", "ais"];
+
+/**
+ * Upgrade a document from version from to from + 1. Each step only touches
+ * what changed in that version and leaves everything else as it found it.
+ */
+pub fn step(kind: Kind, from: u32, data: &mut Json) -> Result<(), SchemaError> {
+	match from {
+		1 => first_release(kind, data),
+		_ => Ok(())
+	}
+}
+
+/**
+ * Work out the version of a document saved before envelopes existed from the
+ * fields it has. Every such document was saved by version 1.
+ */
+pub fn detect(kind: Kind, data: &Json) -> Option<u32> {
+	let fields: &[&str] = match kind {
+		Kind::Map => &["layers"],
+		Kind::Entity => &["id", "t"],
+		Kind::World => &["map", "entities"]
+	};
+
+	if fields.iter().all(|&field| data.find(field).is_some()) {
+		Some(1)
+	} else {
+		None
+	}
+}
+
+/**
+ * 1 to 2, where saved documents gained their envelope.
+ *
+ * Entities became a set of components. The type, position and size every
+ * entity had are now Type, Position and Body components and their ids are an
+ * index and generation. A World keeps each kind of component in its own store
+ * indexed by entity, with the ids handed out by its allocator.
+ *
+ * Maps gained a tile registry, which is empty until the server loads one, and
+ * record their tileset, spawn points and portals. Every map before then was
+ * drawn from the grass tileset.
+ */
+fn first_release(kind: Kind, data: &mut Json) -> Result<(), SchemaError> {
+	match kind {
+		Kind::Map => map(try!(as_object(data, "map"))),
+		Kind::Entity => {
+			let entity = try!(as_object(data, "entity"));
+			let id = try!(old_id(entity));
+
+			if id > u32::MAX as u64 {
+				return Err(SchemaError::Malformed(1, "id"));
+			}
+
+			let components = try!(split_entity(entity)).into_iter().map(|(_, name, component)| variant(name, component)).collect();
+			entity.insert("id".to_string(), entity_id(id));
+			entity.insert("components".to_string(), Json::Array(components));
+			Ok(())
+		},
+		Kind::World => {
+			let world = try!(as_object(data, "world"));
+			try!(map(try!(world.get_mut("map").ok_or(SchemaError::Malformed(1, "map")).and_then(|map| as_object(map, "map")))));
+
+			let entities = match try!(take(world, "entities")) {
+				Json::Array(entities) => entities,
+				_ => return Err(SchemaError::Malformed(1, "entities"))
+			};
+
+			//Old ids could be anything, they are packed down to the first indices in the order saved
+			let mut used = HashSet::new();
+			let mut stores: Vec<Vec<Json>> = STORES.iter().map(|_| Vec::new()).collect();
+
+			for (index, mut entity) in entities.into_iter().enumerate() {
+				let entity = try!(as_object(&mut entity, "entity"));
+
+				if !used.insert(try!(old_id(entity))) {
+					return Err(SchemaError::Malformed(1, "id"));
+				}
+
+				for (store, _, component) in try!(split_entity(entity)) {
+					let slots = &mut stores[STORES.iter().position(|&name| name == store).unwrap_or(0)];
+
+					while slots.len() < index {
+						slots.push(Json::Null);
+					}
+
+					slots.push(Json::Array(vec![entity_id(index as u64), component]));
+				}
+			}
+
+			let components = STORES.iter().zip(stores.into_iter()).map(|(&name, slots)| {
+				(name, json_object(vec![("slots", Json::Array(slots))]))
+			}).collect();
+
+			let ids = json_object(vec![
+				("slots", Json::Array((0..used.len()).map(|_| json_object(vec![
+					("generation", Json::U64(0)),
+					("alive", Json::Boolean(true))
+				])).collect())),
+				("free", Json::Array(Vec::new()))
+			]);
+
+			world.insert("components".to_string(), json_object(components));
+			world.insert("ids".to_string(), ids);
+			Ok(())
+		}
+	}
+}
+
+fn map(map: &mut Object) -> Result<(), SchemaError> {
+	if map.get("layers").and_then(|layers| layers.as_array()).is_none() {
+		return Err(SchemaError::Malformed(1, "layers"));
+	}
+
+	//Fields a map already has are kept
+	map.entry("registry".to_string()).or_insert_with(|| json_object(vec![("tiles", Json::Array(Vec::new()))]));
+	map.entry("tileset".to_string()).or_insert_with(|| json_object(vec![
+		("name", Json::String("grass".to_string())),
+		("columns", Json::U64(16)),
+		("count", Json::U64(672))
+	]));
+	map.entry("spawns".to_string()).or_insert_with(|| Json::Array(Vec::new()));
+	map.entry("portals".to_string()).or_insert_with(|| Json::Array(Vec::new()));
+	Ok(())
+}
+
+fn old_id(entity: &mut Object) -> Result<u64, SchemaError> {
+	take(entity, "id").and_then(|id| id.as_u64().ok_or(SchemaError::Malformed(1, "id")))
+}
+
+/**
+ * Take a version 1 entity apart into its components, each with the store
+ * it belongs in and its ComponentData variant
+ */
+fn split_entity(entity: &mut Object) -> Result<Vec<(&'static str, &'static str, Json)>, SchemaError> {
+	let kind = try!(take(entity, "t"));
+	let pos = try!(take(entity, "pos"));
+	let size = try!(take(entity, "size"));
+
+	Ok(vec![
+		("types", "Type", kind),
+		("positions", "Position", json_object(vec![("pos", pos)])),
+		("bodies", "Body", json_object(vec![("size", size)]))
+	])
+}
+
+fn as_object<'a>(data: &'a mut Json, name: &'static str) -> Result<&'a mut Object, SchemaError> {
+	data.as_object_mut().ok_or(SchemaError::Malformed(1, name))
+}
+
+fn take(object: &mut Object, name: &'static str) -> Result<Json, SchemaError> {
+	object.remove(name).ok_or(SchemaError::Malformed(1, name))
+}
+
+fn entity_id(index: u64) -> Json {
+	json_object(vec![("index", Json::U64(index)), ("generation", Json::U64(0))])
+}
+
+/**
+ * How rustc_serialize writes an enum variant holding a single value
+ */
+fn variant(name: &str, value: Json) -> Json {
+	json_object(vec![("variant", Json::String(name.to_string())), ("fields", Json::Array(vec![value]))])
+}
@@ -0,0 +1,117 @@
+use rustc_serialize::{json, Encodable, Decodable};
+use rustc_serialize::json::Json;
+
+mod migrations;
+
+/**
+ * Bumped, with a migration added, whenever a Map, World or Entity changes how it is saved.
+ * Version 1 is everything saved before documents were versioned.
+ */
+pub const SCHEMA_VERSION: u32 = 2;
+
+/**
+ * What a saved document holds
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+	Map,
+	World,
+	Entity
+}
+
+impl Kind {
+	pub fn name(&self) -> &'static str {
+		match *self {
+			Kind::Map => "map",
+			Kind::World => "world",
+			Kind::Entity => "entity"
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum SchemaError {
+	Parse(json::ParserError),
+	Decode(json::DecoderError),
+
+	//The envelope holds a different kind of document
+	WrongKind(String),
+
+	//Saved by a newer build than this one
+	TooNew(u32),
+
+	//A document without an envelope that matches no version we know
+	Unrecognised,
+
+	//The document didn't look like the version it claimed, at the field named
+	Malformed(u32, &'static str)
+}
+
+/**
+ * Save a value wrapped in an envelope recording its kind and the schema version
+ */
+pub fn encode<T: Encodable>(kind: Kind, value: &T) -> Result<String, json::EncoderError> {
+	let data = try!(json::encode(value));
+	Ok(format!("{{\"kind\":\"{}\",\"version\":{},\"data\":{}}}", kind.name(), SCHEMA_VERSION, data))
+}
+
+/**
+ * Load a saved value, upgrading it from whichever version it was saved at
+ */
+pub fn decode<T: Decodable>(kind: Kind, text: &str) -> Result<T, SchemaError> {
+	decode_json(kind, try!(Json::from_str(text).map_err(SchemaError::Parse)))
+}
+
+/**
+ * As decode, for a document already parsed, such as one inside a larger file
+ */
+pub fn decode_json<T: Decodable>(kind: Kind, document: Json) -> Result<T, SchemaError> {
+	let data = try!(upgrade(kind, document));
+	let mut decoder = json::Decoder::new(data);
+	Decodable::decode(&mut decoder).map_err(SchemaError::Decode)
+}
+
+/**
+ * Unwrap a document and run every migration from its version up to SCHEMA_VERSION.
+ * Documents saved before envelopes existed have their version worked out from their fields.
+ */
+pub fn upgrade(kind: Kind, document: Json) -> Result<Json, SchemaError> {
+	let (version, mut data) = if is_envelope(&document) {
+		try!(open(kind, document))
+	} else {
+		(try!(migrations::detect(kind, &document).ok_or(SchemaError::Unrecognised)), document)
+	};
+
+	if version > SCHEMA_VERSION {
+		return Err(SchemaError::TooNew(version));
+	}
+
+	for from in version..SCHEMA_VERSION {
+		try!(migrations::step(kind, from, &mut data));
+	}
+
+	Ok(data)
+}
+
+fn is_envelope(document: &Json) -> bool {
+	document.find("kind").is_some() && document.find("version").is_some() && document.find("data").is_some()
+}
+
+/**
+ * The version and data of an enveloped document
+ */
+fn open(kind: Kind, document: Json) -> Result<(u32, Json), SchemaError> {
+	let mut object = match document {
+		Json::Object(object) => object,
+		_ => return Err(SchemaError::Unrecognised)
+	};
+
+	let found = object.get("kind").and_then(|found| found.as_string()).unwrap_or("").to_string();
+
+	if found != kind.name() {
+		return Err(SchemaError::WrongKind(found));
+	}
+
+	let version = try!(object.get("version").and_then(|version| version.as_u64()).ok_or(SchemaError::Malformed(0, "version")));
+	Ok((version as u32, object.remove("data").unwrap_or(Json::Null)))
+}
@@ -1,10 +1,49 @@
 use std::io;
+use std::io::Write;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
 use std::result::Result;
 use std::error::Error;
+use std::time::Duration;
+use rustc_serialize::json::Json;
 
 pub fn to_io<Q, T>(msg: Result<Q, T>) -> io::Result<Q> where T: Error {
 	match msg {
 		Ok(e) => Ok(e),
 		Err(e) => Err(io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))
 	}
-}
\ No newline at end of file
+}
+
+pub fn seconds(time: Duration) -> f64 {
+	time.as_secs() as f64 + (time.subsec_nanos() as f64 / 1_000_000_000.0)
+}
+
+pub fn json_object(fields: Vec<(&str, Json)>) -> Json {
+	let mut object = BTreeMap::new();
+
+	for (key, value) in fields {
+		object.insert(key.to_string(), value);
+	}
+
+	Json::Object(object)
+}
+
+/**
+ * Replace the file at path with data. It is written to path.tmp first and
+ * renamed over the old file, so a crash part way through never leaves it half written.
+ */
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+	let mut tmp = path.as_os_str().to_owned();
+	tmp.push(".tmp");
+	let tmp = PathBuf::from(tmp);
+
+	{
+		let mut file = try!(File::create(&tmp));
+		try!(file.write_all(data));
+		try!(file.sync_all());
+	}
+
+	fs::rename(&tmp, path)
+}
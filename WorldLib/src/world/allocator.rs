@@ -0,0 +1,171 @@
+use rustc_serialize::{Decodable, Decoder};
+use entity::EntityID;
+
+#[derive(RustcEncodable, RustcDecodable, Debug, Clone)]
+struct Slot {
+	generation: u32,
+	alive: bool
+}
+
+/**
+ * Hands out generational entity IDs. Freeing an ID bumps the generation of its
+ * slot so any copies of the old ID no longer match when the slot is reused.
+ */
+#[derive(RustcEncodable, Debug, Clone)]
+pub struct Allocator {
+	slots: Vec<Slot>,
+	free: Vec<u32>
+}
+
+/**
+ * An Allocator as saved, checked before it is trusted
+ */
+#[derive(RustcDecodable)]
+struct Saved {
+	slots: Vec<Slot>,
+	free: Vec<u32>
+}
+
+impl Decodable for Allocator {
+	fn decode<D: Decoder>(d: &mut D) -> Result<Allocator, D::Error> {
+		let saved = try!(Saved::decode(d));
+		let mut listed = vec![false; saved.slots.len()];
+
+		//Each free index must be a dead slot listed once, or allocate would hand out a live ID
+		for &index in &saved.free {
+			match saved.slots.get(index as usize) {
+				Some(slot) if !slot.alive && !listed[index as usize] => listed[index as usize] = true,
+				_ => return Err(d.error(&format!("free index {} is not a dead slot listed once", index)))
+			}
+		}
+
+		Ok(Allocator {
+			slots: saved.slots,
+			free: saved.free
+		})
+	}
+}
+
+impl Allocator {
+
+	pub fn new() -> Allocator {
+		Allocator {
+			slots: Vec::new(),
+			free: Vec::new()
+		}
+	}
+
+	pub fn allocate(&mut self) -> EntityID {
+		if let Some(index) = self.free.pop() {
+			let slot = &mut self.slots[index as usize];
+			slot.alive = true;
+			EntityID { index: index, generation: slot.generation }
+		} else {
+			self.slots.push(Slot { generation: 0, alive: true });
+			EntityID { index: (self.slots.len() - 1) as u32, generation: 0 }
+		}
+	}
+
+	pub fn is_alive(&self, id: EntityID) -> bool {
+		match self.slots.get(id.index as usize) {
+			Some(slot) => slot.alive && slot.generation == id.generation,
+			None => false
+		}
+	}
+
+	pub fn alive(&self) -> Vec<EntityID> {
+		self.slots.iter().enumerate()
+			.filter(|&(_, slot)| slot.alive)
+			.map(|(index, slot)| EntityID { index: index as u32, generation: slot.generation })
+			.collect()
+	}
+
+	/**
+	 * Release an ID, returns false if it was already stale
+	 */
+	pub fn free(&mut self, id: EntityID) -> bool {
+		if !self.is_alive(id) {
+			return false;
+		}
+
+		let slot = &mut self.slots[id.index as usize];
+		slot.alive = false;
+		slot.generation = slot.generation.wrapping_add(1);
+		self.free.push(id.index);
+		true
+	}
+
+	/**
+	 * Mark an ID allocated elsewhere (a server or a save) as in use. Returns
+	 * the ID that previously held the slot if it was alive under another generation.
+	 */
+	pub fn reserve(&mut self, id: EntityID) -> Option<EntityID> {
+		let index = id.index as usize;
+
+		while self.slots.len() <= index {
+			self.free.push(self.slots.len() as u32);
+			self.slots.push(Slot { generation: 0, alive: false });
+		}
+
+		let previous = {
+			let slot = &self.slots[index];
+			if slot.alive && slot.generation != id.generation {
+				Some(EntityID { index: id.index, generation: slot.generation })
+			} else {
+				None
+			}
+		};
+
+		self.slots[index] = Slot { generation: id.generation, alive: true };
+		self.free.retain(|&free| free != id.index);
+		previous
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rustc_serialize::json;
+	use entity::EntityID;
+
+	#[test]
+	fn reuses_freed_indices_with_a_new_generation() {
+		let mut ids = Allocator::new();
+		let first = ids.allocate();
+		let second = ids.allocate();
+
+		assert!(ids.free(first));
+		let reused = ids.allocate();
+
+		assert_eq!(reused, EntityID { index: first.index, generation: first.generation + 1 });
+		assert_eq!(ids.allocate(), EntityID { index: 2, generation: 0 });
+		assert_eq!(ids.alive().len(), 3);
+		assert!(ids.is_alive(second));
+	}
+
+	#[test]
+	fn rejects_stale_ids() {
+		let mut ids = Allocator::new();
+		let old = ids.allocate();
+		ids.free(old);
+		let new = ids.allocate();
+
+		assert!(!ids.is_alive(old));
+		assert!(!ids.free(old));
+		assert!(ids.is_alive(new));
+		assert!(!ids.is_alive(EntityID { index: 7, generation: 0 }));
+	}
+
+	#[test]
+	fn checks_free_indices_when_decoding() {
+		let slots = "[{\"generation\":1,\"alive\":false},{\"generation\":0,\"alive\":true}]";
+		let saved = |free: &str| format!("{{\"slots\":{},\"free\":{}}}", slots, free);
+
+		let mut ids: Allocator = json::decode(&saved("[0]")).unwrap();
+		assert_eq!(ids.allocate(), EntityID { index: 0, generation: 1 });
+
+		for free in ["[5]", "[1]", "[0,0]"].iter() {
+			assert!(json::decode::<Allocator>(&saved(free)).is_err(), "free {}", free);
+		}
+	}
+}
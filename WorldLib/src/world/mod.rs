@@ -1,48 +1,342 @@
-use rustc_serialize::json;
-use entity::{Entity, EntityID};
-use map::Map;
+use rustc_serialize::{Encodable, Encoder, Decodable, Decoder};
+use entity::{Entity, EntityID, Component, Components, ComponentData, ComponentKind, Iter, Position, Body, Ai, Behaviour};
+use map::{Map, Portal};
+use schema;
+use schema::{Kind, SchemaError};
+use math::{Vec2, Rect};
+use path;
+use fov;
+use path::TilePos;
+use utils::seconds;
 use std::time::Duration;
 
-#[derive(RustcEncodable, RustcDecodable, Debug, Clone)]
+mod allocator;
+mod systems;
+mod spatial;
+
+pub use self::allocator::Allocator;
+pub use self::spatial::SpatialGrid;
+
+/**
+ * Components should be changed through the World so the spatial index stays
+ * in step, it is rebuilt rather than sent when a World is encoded.
+ */
+#[derive(Debug, Clone)]
 pub struct World {
 	pub map: Map,
-	pub entities: Vec<Entity>
+	pub components: Components,
+	ids: Allocator,
+	spatial: SpatialGrid
 }
 
 impl World {
 
 	pub fn new(map: Map) -> World {
 		World {
-			entities: Vec::new(),
-			map: map
+			map: map,
+			components: Components::new(),
+			ids: Allocator::new(),
+			spatial: SpatialGrid::new()
+		}
+	}
+
+	/**
+	 * Create a new entity with a freshly allocated ID from a set of components
+	 */
+	pub fn spawn(&mut self, components: Vec<ComponentData>) -> EntityID {
+		let id = self.ids.allocate();
+
+		for component in components {
+			self.components.set(id, component);
+		}
+
+		self.reindex(id);
+		id
+	}
+
+	pub fn contains(&self, id: EntityID) -> bool {
+		self.ids.is_alive(id)
+	}
+
+	/**
+	 * Every entity currently alive
+	 */
+	pub fn entities(&self) -> Vec<EntityID> {
+		self.ids.alive()
+	}
+
+	/**
+	 * Gather an entity and all of its components
+	 */
+	pub fn entity(&self, id: EntityID) -> Option<Entity> {
+		if !self.contains(id) {
+			return None;
+		}
+
+		Some(Entity {
+			id: id,
+			components: self.components.collect(id)
+		})
+	}
+
+	pub fn get<T: Component>(&self, id: EntityID) -> Option<&T> {
+		T::store(&self.components).get(id)
+	}
+
+	pub fn get_mut<T: Component>(&mut self, id: EntityID) -> Option<&mut T> {
+		T::store_mut(&mut self.components).get_mut(id)
+	}
+
+	/**
+	 * Attach or replace a component, ignored if the entity no longer exists
+	 */
+	pub fn insert<T: Component>(&mut self, id: EntityID, component: T) {
+		if self.contains(id) {
+			T::store_mut(&mut self.components).insert(id, component);
+
+			if moves(T::kind()) {
+				self.reindex(id);
+			}
+		}
+	}
+
+	pub fn remove_component<T: Component>(&mut self, id: EntityID) -> Option<T> {
+		let removed = T::store_mut(&mut self.components).remove(id);
+
+		if moves(T::kind()) {
+			self.reindex(id);
 		}
+
+		removed
+	}
+
+	/**
+	 * Every entity with a T and that T
+	 */
+	pub fn query<T: Component>(&self) -> Iter<T> {
+		T::store(&self.components).iter()
+	}
+
+	/**
+	 * Every entity with both an A and a B
+	 */
+	pub fn join<A: Component, B: Component>(&self) -> Vec<(EntityID, &A, &B)> {
+		let other = B::store(&self.components);
+		self.query::<A>()
+			.filter_map(|(id, a)| other.get(id).map(|b| (id, a, b)))
+			.collect()
 	}
 
+	/**
+	 * Apply a single replicated component
+	 */
+	pub fn apply(&mut self, id: EntityID, component: ComponentData) {
+		if self.contains(id) {
+			let kind = component.kind();
+			self.components.set(id, component);
+
+			if moves(kind) {
+				self.reindex(id);
+			}
+		}
+	}
+
+	pub fn remove_kind(&mut self, id: EntityID, kind: ComponentKind) {
+		self.components.remove(id, kind);
+
+		if moves(kind) {
+			self.reindex(id);
+		}
+	}
+
+	/**
+	 * Update an entity or insert one replicated from elsewhere. An inserted
+	 * entity takes over its ID slot, evicting any stale entity that held it.
+	 */
 	pub fn update_or_insert(&mut self, entity: &Entity) {
-		if let Some(pos) = self.entities.iter().position(|x| x.id == entity.id) {
-			self.entities[pos] = entity.clone();
-		} else {
-			self.entities.push(entity.clone());
+		if !self.contains(entity.id) {
+			if let Some(stale) = self.ids.reserve(entity.id) {
+				self.components.remove_all(stale);
+				self.spatial.remove(stale);
+			}
+		}
+
+		for component in &entity.components {
+			self.components.set(entity.id, component.clone());
 		}
+
+		self.reindex(entity.id);
 	}
 
 	pub fn remove(&mut self, id: EntityID) {
-		if let Some(pos) = self.entities.iter().position(|x| x.id == id) {
-			self.entities.remove(pos);
+		if self.ids.free(id) {
+			self.components.remove_all(id);
+			self.spatial.remove(id);
+		}
+	}
+
+	/**
+	 * Place an entity in the spatial index by its Position and Body
+	 */
+	fn reindex(&mut self, id: EntityID) {
+		match self.bounds(id) {
+			Some(bounds) => self.spatial.update(id, bounds),
+			None => self.spatial.remove(id)
 		}
 	}
 
-	pub fn update(&mut self, utime: Duration) {
-		for entity in &mut self.entities {
-			entity.update(utime);
+	fn reindex_all(&mut self) {
+		self.spatial.clear();
+
+		for id in self.entities() {
+			self.reindex(id);
 		}
 	}
 
-	pub fn from_json(t: &str) -> World {
-		json::decode(t).unwrap()
+	/**
+	 * Every entity whose body comes within radius of pos
+	 */
+	pub fn near(&self, pos: Vec2, radius: f64) -> Vec<EntityID> {
+		self.spatial.radius(pos, radius)
+	}
+
+	/**
+	 * Every entity whose body overlaps the rectangle
+	 */
+	pub fn in_rect(&self, rect: &Rect) -> Vec<EntityID> {
+		self.spatial.rect(rect)
+	}
+
+	/**
+	 * Every entity standing on the tile
+	 */
+	pub fn on_tile(&self, x: usize, y: usize) -> Vec<EntityID> {
+		let half = Vec2::new(self.map.tile_width / 2.0, self.map.tile_height);
+		let diamond = Rect::new(self.map.tile_to_world(x, y) - half, half * 2.0);
+
+		self.in_rect(&diamond)
+			.into_iter()
+			.filter(|&id| self.tile(id) == Some((x, y)))
+			.collect()
+	}
+
+	/**
+	 * The box an entity takes up, an entity without a Body is a point
+	 */
+	pub fn bounds(&self, id: EntityID) -> Option<Rect> {
+		let size = self.get::<Body>(id).map(|body| body.size).unwrap_or(Vec2::zero());
+		self.get::<Position>(id).map(|position| Rect::new(position.pos, size))
+	}
+
+	/**
+	 * Where the entity stands, the bottom centre of its body
+	 */
+	pub fn feet(&self, id: EntityID) -> Option<Vec2> {
+		self.bounds(id).map(|bounds| bounds.bottom_centre())
+	}
+
+	/**
+	 * The tile an entity is standing on
+	 */
+	pub fn tile(&self, id: EntityID) -> Option<TilePos> {
+		self.feet(id).and_then(|feet| self.map.world_to_tile(feet))
+	}
+
+	/**
+	 * The portal the entity is standing in, if any
+	 */
+	pub fn portal(&self, id: EntityID) -> Option<&Portal> {
+		self.feet(id).and_then(|feet| self.map.portal_at(feet))
+	}
+
+	/**
+	 * Whether a can see b, for NPC aggro and ranged attacks
+	 */
+	pub fn can_see(&self, a: EntityID, b: EntityID) -> bool {
+		match (self.tile(a), self.tile(b)) {
+			(Some(from), Some(to)) => fov::line_of_sight(&self.map, from, to),
+			_ => false
+		}
+	}
+
+	/**
+	 * Path the entity from the tile it stands on to goal and have it walk
+	 * there. Returns false, leaving the entity alone, if there is no route.
+	 */
+	pub fn route(&mut self, id: EntityID, goal: TilePos) -> bool {
+		let start = match self.tile(id) {
+			Some(start) => start,
+			None => return false
+		};
+
+		let tiles = match path::find(&self.map, start, goal) {
+			Some(tiles) => tiles,
+			None => return false
+		};
+
+		let size = self.get::<Body>(id).map(|body| body.size).unwrap_or(Vec2::zero());
+		let stand = Vec2::new(size.x / 2.0, size.y);
+
+		let points = tiles.into_iter()
+			.skip(1)
+			.map(|(x, y)| self.map.tile_to_world(x, y) - stand)
+			.collect();
+
+		self.insert(id, Ai { behaviour: Behaviour::Walk(points) });
+		true
+	}
+
+	/**
+	 * Run every system for utime, returning each component that changed
+	 */
+	pub fn update(&mut self, utime: Duration) -> Vec<(EntityID, ComponentKind)> {
+		let dt = seconds(utime);
+		let mut changed = systems::ai(self, dt);
+		changed.extend(systems::movement(self, dt));
+		changed
+	}
+
+	/**
+	 * Load a saved world, upgrading it if it was saved by an older version
+	 */
+	pub fn from_json(t: &str) -> Result<World, SchemaError> {
+		schema::decode(Kind::World, t)
 	}
 
 	pub fn as_json(&self) -> String {
-		json::encode(self).unwrap()
+		schema::encode(Kind::World, self).unwrap()
 	}
-}
\ No newline at end of file
+}
+
+/**
+ * Is the entities box in the spatial index affected by a change to kind
+ */
+fn moves(kind: ComponentKind) -> bool {
+	kind == Position::kind() || kind == Body::kind()
+}
+
+impl Encodable for World {
+	fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+		s.emit_struct("World", 3, |s| {
+			try!(s.emit_struct_field("map", 0, |s| self.map.encode(s)));
+			try!(s.emit_struct_field("components", 1, |s| self.components.encode(s)));
+			s.emit_struct_field("ids", 2, |s| self.ids.encode(s))
+		})
+	}
+}
+
+impl Decodable for World {
+	fn decode<D: Decoder>(d: &mut D) -> Result<World, D::Error> {
+		d.read_struct("World", 3, |d| {
+			let mut world = World {
+				map: try!(d.read_struct_field("map", 0, Decodable::decode)),
+				components: try!(d.read_struct_field("components", 1, Decodable::decode)),
+				ids: try!(d.read_struct_field("ids", 2, Decodable::decode)),
+				spatial: SpatialGrid::new()
+			};
+
+			world.reindex_all();
+			Ok(world)
+		})
+	}
+}
@@ -0,0 +1,324 @@
+use std::collections::{HashMap, HashSet};
+use entity::EntityID;
+use math::{Vec2, Rect};
+
+type Cell = (i64, i64);
+
+/**
+ * Width of a grid cell in world units
+ */
+pub const CELL_SIZE: f64 = 128.0;
+
+/**
+ * An entity's box and the range of cells it covers
+ */
+#[derive(Debug, Clone, Copy)]
+struct Placed {
+	bounds: Rect,
+	min: Cell,
+	max: Cell
+}
+
+/**
+ * A uniform grid over entity boxes so spatial queries only look at the
+ * entities in nearby cells. A box is listed in every cell it overlaps.
+ */
+#[derive(Debug, Clone)]
+pub struct SpatialGrid {
+	cells: HashMap<Cell, Vec<EntityID>>,
+	placed: HashMap<EntityID, Placed>
+}
+
+fn cell(point: Vec2) -> Cell {
+	((point.x / CELL_SIZE).floor() as i64, (point.y / CELL_SIZE).floor() as i64)
+}
+
+impl SpatialGrid {
+
+	pub fn new() -> SpatialGrid {
+		SpatialGrid {
+			cells: HashMap::new(),
+			placed: HashMap::new()
+		}
+	}
+
+	/**
+	 * Insert an entity or move it
+	 */
+	pub fn update(&mut self, id: EntityID, bounds: Rect) {
+		let min = cell(bounds.min());
+		let max = cell(bounds.max());
+
+		let placed = Placed {
+			bounds: bounds,
+			min: min,
+			max: max
+		};
+
+		match self.placed.insert(id, placed) {
+			Some(old) if old.min == min && old.max == max => return,
+			Some(old) => self.unlink(id, old),
+			None => {}
+		}
+
+		for y in min.1..(max.1 + 1) {
+			for x in min.0..(max.0 + 1) {
+				self.cells.entry((x, y)).or_insert_with(Vec::new).push(id);
+			}
+		}
+	}
+
+	pub fn remove(&mut self, id: EntityID) {
+		if let Some(old) = self.placed.remove(&id) {
+			self.unlink(id, old);
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.cells.clear();
+		self.placed.clear();
+	}
+
+	fn unlink(&mut self, id: EntityID, old: Placed) {
+		for y in old.min.1..(old.max.1 + 1) {
+			for x in old.min.0..(old.max.0 + 1) {
+				let empty = match self.cells.get_mut(&(x, y)) {
+					Some(ids) => {
+						ids.retain(|&other| other != id);
+						ids.is_empty()
+					},
+					None => false
+				};
+
+				if empty {
+					self.cells.remove(&(x, y));
+				}
+			}
+		}
+	}
+
+	/**
+	 * Every entity listed in a cell overlapping area, without duplicates
+	 */
+	fn candidates(&self, area: &Rect) -> Vec<EntityID> {
+		let (min_x, min_y) = cell(area.min());
+		let (max_x, max_y) = cell(area.max());
+		let mut seen = HashSet::new();
+		let mut found = Vec::new();
+
+		for cell_y in min_y..(max_y + 1) {
+			for cell_x in min_x..(max_x + 1) {
+				if let Some(ids) = self.cells.get(&(cell_x, cell_y)) {
+					for &id in ids {
+						if seen.insert(id) {
+							found.push(id);
+						}
+					}
+				}
+			}
+		}
+
+		found
+	}
+
+	/**
+	 * Every entity whose box comes within radius of pos
+	 */
+	pub fn radius(&self, pos: Vec2, radius: f64) -> Vec<EntityID> {
+		self.candidates(&Rect::new(pos, Vec2::zero()).expand(radius))
+			.into_iter()
+			.filter(|id| self.placed[id].bounds.clamp(pos).distance(pos) <= radius)
+			.collect()
+	}
+
+	/**
+	 * Every entity whose box overlaps area
+	 */
+	pub fn rect(&self, area: &Rect) -> Vec<EntityID> {
+		self.candidates(area)
+			.into_iter()
+			.filter(|id| self.placed[id].bounds.touches(area))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use entity::{EntityID, ComponentData, Position, Body};
+	use map::Map;
+	use math::{Vec2, Rect};
+	use world::World;
+	use map::gen::Rng;
+	use fov::VIEW_RADIUS;
+	use utils::seconds;
+	use std::time::Instant;
+
+	fn id(index: u32) -> EntityID {
+		EntityID { index: index, generation: 0 }
+	}
+
+	fn bounds(x: f64, y: f64, width: f64, height: f64) -> Rect {
+		Rect::new(Vec2::new(x, y), Vec2::new(width, height))
+	}
+
+	fn sorted(mut ids: Vec<EntityID>) -> Vec<EntityID> {
+		ids.sort_by_key(|id| id.index);
+		ids
+	}
+
+	#[test]
+	fn radius_reaches_the_edge_of_a_box() {
+		let mut grid = SpatialGrid::new();
+		grid.update(id(1), bounds(200.0, 0.0, 32.0, 32.0));
+		grid.update(id(2), bounds(10.0, 10.0, 0.0, 0.0));
+
+		assert_eq!(grid.radius(Vec2::new(150.0, 16.0), 50.0), vec![id(1)]);
+		assert!(grid.radius(Vec2::new(150.0, 16.0), 49.0).is_empty());
+		assert_eq!(sorted(grid.radius(Vec2::new(150.0, 16.0), 141.0)), vec![id(1), id(2)]);
+	}
+
+	#[test]
+	fn rect_finds_overlapping_boxes() {
+		let mut grid = SpatialGrid::new();
+		grid.update(id(1), bounds(0.0, 0.0, 32.0, 32.0));
+		grid.update(id(2), bounds(300.0, 300.0, 32.0, 32.0));
+		grid.update(id(3), bounds(-200.0, -200.0, 32.0, 32.0));
+
+		assert_eq!(grid.rect(&bounds(16.0, 16.0, 100.0, 100.0)), vec![id(1)]);
+		assert_eq!(grid.rect(&bounds(-300.0, -300.0, 150.0, 150.0)), vec![id(3)]);
+		assert_eq!(sorted(grid.rect(&bounds(-500.0, -500.0, 1000.0, 1000.0))), vec![id(1), id(2), id(3)]);
+		assert!(grid.rect(&bounds(100.0, 100.0, 50.0, 50.0)).is_empty());
+	}
+
+	#[test]
+	fn update_moves_an_entity_between_cells() {
+		let mut grid = SpatialGrid::new();
+		grid.update(id(1), bounds(10.0, 10.0, 32.0, 32.0));
+		grid.update(id(1), bounds(1100.0, 1100.0, 32.0, 32.0));
+
+		assert!(grid.radius(Vec2::new(10.0, 10.0), 20.0).is_empty());
+		assert_eq!(grid.radius(Vec2::new(1100.0, 1100.0), 20.0), vec![id(1)]);
+		assert_eq!(grid.cells.keys().cloned().collect::<Vec<Cell>>(), vec![cell(Vec2::new(1100.0, 1100.0))]);
+
+		//Moving within its cells only changes its box
+		grid.update(id(1), bounds(1110.0, 1110.0, 32.0, 32.0));
+		assert!(grid.radius(Vec2::new(1100.0, 1100.0), 5.0).is_empty());
+		assert_eq!(grid.radius(Vec2::new(1110.0, 1110.0), 5.0), vec![id(1)]);
+	}
+
+	#[test]
+	fn boxes_straddling_cells_are_found_from_each_once() {
+		let mut grid = SpatialGrid::new();
+		let straddling = bounds(CELL_SIZE - 10.0, CELL_SIZE - 10.0, CELL_SIZE + 20.0, CELL_SIZE + 20.0);
+		grid.update(id(1), straddling);
+
+		assert_eq!(grid.cells.len(), 9);
+
+		for &x in [0.0, 1.5, 2.5].iter() {
+			for &y in [0.0, 1.5, 2.5].iter() {
+				let corner = Vec2::new(x * CELL_SIZE, y * CELL_SIZE);
+				assert_eq!(grid.radius(straddling.clamp(corner), 1.0), vec![id(1)], "near {:?}", corner);
+			}
+		}
+
+		assert_eq!(grid.rect(&bounds(0.0, 0.0, CELL_SIZE * 3.0, CELL_SIZE * 3.0)), vec![id(1)]);
+	}
+
+	#[test]
+	fn removed_entities_are_never_returned() {
+		let mut grid = SpatialGrid::new();
+		grid.update(id(1), bounds(0.0, 0.0, 300.0, 300.0));
+		grid.update(id(2), bounds(50.0, 50.0, 32.0, 32.0));
+		grid.remove(id(1));
+
+		assert_eq!(grid.radius(Vec2::new(60.0, 60.0), 500.0), vec![id(2)]);
+		assert_eq!(grid.rect(&bounds(-100.0, -100.0, 600.0, 600.0)), vec![id(2)]);
+
+		grid.remove(id(2));
+		grid.remove(id(2));
+
+		assert!(grid.rect(&bounds(-100.0, -100.0, 600.0, 600.0)).is_empty());
+		assert!(grid.cells.is_empty() && grid.placed.is_empty());
+	}
+
+	#[test]
+	fn on_tile_finds_who_is_standing_there() {
+		let mut world = World::new(Map::new(16, 32));
+		let (x, y) = (3, 5);
+		let centre = world.map.tile_to_world(x, y);
+		let body = Vec2::new(32.0, 48.0);
+
+		//Positions are the top left of the body, tiles are found by its feet
+		let standing = |feet: Vec2| vec![
+			ComponentData::Position(Position { pos: feet - Vec2::new(body.x / 2.0, body.y) }),
+			ComponentData::Body(Body { size: body })
+		];
+
+		let on = world.spawn(standing(centre));
+		let beside = world.spawn(standing(world.map.tile_to_world(x + 1, y)));
+
+		assert_eq!(world.on_tile(x, y), vec![on]);
+
+		world.insert(beside, Position { pos: centre - Vec2::new(body.x / 2.0, body.y) });
+		assert_eq!(sorted(world.on_tile(x, y)), sorted(vec![on, beside]));
+
+		world.remove(on);
+		assert_eq!(world.on_tile(x, y), vec![beside]);
+	}
+
+	/**
+	 * Times the grid against scanning every entity, as World did before it kept
+	 * one, for area of interest sized radii and collision sized boxes. Seeded so
+	 * every run places the same entities and asks the same questions.
+	 *
+	 *   cargo test --release grid_against_a_linear_scan -- --ignored --nocapture
+	 */
+	#[test]
+	#[ignore]
+	fn grid_against_a_linear_scan() {
+		const ENTITIES: u32 = 20000;
+		const QUERIES: usize = 2000;
+		const WORLD_SIZE: f64 = 16384.0;
+
+		let mut rng = Rng::new(17);
+		let mut grid = SpatialGrid::new();
+		let mut entities = Vec::new();
+
+		for index in 0..ENTITIES {
+			let placed = bounds(rng.next_f64() * WORLD_SIZE, rng.next_f64() * WORLD_SIZE, 32.0, 48.0);
+			grid.update(id(index), placed);
+			entities.push((id(index), placed));
+		}
+
+		let points: Vec<Vec2> = (0..QUERIES).map(|_| Vec2::new(rng.next_f64() * WORLD_SIZE, rng.next_f64() * WORLD_SIZE)).collect();
+		let radius = VIEW_RADIUS as f64 * 64.0;
+
+		let scan = |keep: &Fn(&Rect, Vec2) -> bool| -> (f64, Vec<Vec<EntityID>>) {
+			let start = Instant::now();
+			let found = points.iter().map(|&point| {
+				entities.iter().filter(|&&(_, ref placed)| keep(placed, point)).map(|&(id, _)| id).collect()
+			}).collect();
+			(seconds(start.elapsed()), found)
+		};
+
+		let query = |find: &Fn(Vec2) -> Vec<EntityID>| -> (f64, Vec<Vec<EntityID>>) {
+			let start = Instant::now();
+			let found = points.iter().map(|&point| find(point)).collect();
+			(seconds(start.elapsed()), found)
+		};
+
+		let (scan_radius, scanned) = scan(&|placed, point| placed.clamp(point).distance(point) <= radius);
+		let (grid_radius, found) = query(&|point| grid.radius(point, radius));
+		assert!(found.into_iter().zip(scanned).all(|(found, scanned)| sorted(found) == sorted(scanned)));
+
+		let area = |point: Vec2| bounds(point.x, point.y, 64.0, 64.0);
+		let (scan_rect, scanned) = scan(&|placed, point| placed.touches(&area(point)));
+		let (grid_rect, found) = query(&|point| grid.rect(&area(point)));
+		assert!(found.into_iter().zip(scanned).all(|(found, scanned)| sorted(found) == sorted(scanned)));
+
+		println!("{} queries over {} entities", QUERIES, ENTITIES);
+		println!("  radius {}: scan {:.4}s, grid {:.4}s", radius, scan_radius, grid_radius);
+		println!("  64x64 rect: scan {:.4}s, grid {:.4}s", scan_rect, grid_rect);
+	}
+}
@@ -0,0 +1,208 @@
+use entity::{EntityID, ComponentKind, Component, Position, Velocity, Ai, Behaviour, WALK_SPEED};
+use math::Vec2;
+use world::World;
+use collision;
+
+/**
+ * How close an entity needs to get to a patrol point to count as arriving
+ */
+const ARRIVE_DISTANCE: f64 = 2.0;
+
+/**
+ * Velocity that heads from from to to at WALK_SPEED, or stands still once there.
+ * Slows down to land exactly on to rather than stepping past it within dt,
+ * otherwise a step longer than ARRIVE_DISTANCE could hop back and forth over it forever.
+ */
+fn seek(from: Vec2, to: Vec2, dt: f64) -> Vec2 {
+	let offset = to - from;
+	let distance = offset.length();
+
+	if distance < ARRIVE_DISTANCE {
+		Vec2::zero()
+	} else if dt > 0.0 && distance < WALK_SPEED * dt {
+		offset / dt
+	} else {
+		offset.normalize() * WALK_SPEED
+	}
+}
+
+/**
+ * The point an entity walking a route is heading for
+ */
+fn heading(world: &World, id: EntityID) -> Option<Vec2> {
+	match world.get::<Ai>(id).map(|ai| &ai.behaviour) {
+		Some(&Behaviour::Walk(ref points)) => points.first().cloned(),
+		_ => None
+	}
+}
+
+/**
+ * Steer every entity with an Ai by setting its velocity
+ */
+pub fn ai(world: &mut World, dt: f64) -> Vec<(EntityID, ComponentKind)> {
+	let mut changed = Vec::new();
+
+	for id in world.components.ais.ids() {
+		let pos = match world.get::<Position>(id) {
+			Some(position) => position.pos,
+			None => continue
+		};
+
+		let mut behaviour = world.get::<Ai>(id).unwrap().behaviour.clone();
+
+		let vel = match behaviour {
+			Behaviour::Idle => Vec2::zero(),
+			Behaviour::Patrol(ref points, ref mut next) => {
+				if points.is_empty() {
+					Vec2::zero()
+				} else {
+					if points[*next % points.len()].distance(pos) < ARRIVE_DISTANCE {
+						*next = (*next + 1) % points.len();
+					}
+					seek(pos, points[*next % points.len()], dt)
+				}
+			},
+			Behaviour::Follow(target) => match world.get::<Position>(target) {
+				Some(target) => seek(pos, target.pos, dt),
+				None => Vec2::zero()
+			},
+			Behaviour::Walk(ref mut points) => {
+				while !points.is_empty() && points[0].distance(pos) < ARRIVE_DISTANCE {
+					points.remove(0);
+				}
+				points.first().map(|&point| seek(pos, point, dt)).unwrap_or(Vec2::zero())
+			}
+		};
+
+		if behaviour == Behaviour::Walk(Vec::new()) {
+			behaviour = Behaviour::Idle;
+		}
+
+		if world.get::<Ai>(id).unwrap().behaviour != behaviour {
+			world.insert(id, Ai { behaviour: behaviour });
+			changed.push((id, Ai::kind()));
+		}
+
+		if world.get::<Velocity>(id).map(|v| v.vel) != Some(vel) {
+			world.insert(id, Velocity { vel: vel });
+			changed.push((id, Velocity::kind()));
+		}
+	}
+
+	changed
+}
+
+/**
+ * Integrate velocity into position, sliding along anything in the way
+ */
+pub fn movement(world: &mut World, dt: f64) -> Vec<(EntityID, ComponentKind)> {
+	let mut changed = Vec::new();
+
+	for id in world.components.velocities.ids() {
+		let vel = world.get::<Velocity>(id).unwrap().vel;
+
+		if vel.is_zero() {
+			continue;
+		}
+
+		let pos = match world.get::<Position>(id) {
+			Some(position) => position.pos,
+			None => continue
+		};
+
+		let target = collision::slide(world, id, pos, vel * dt);
+
+		if target != pos {
+			world.insert(id, Position { pos: target });
+			changed.push((id, Position::kind()));
+		}
+
+		//Something is in the way of the next point on the route, it will never get there
+		if let Some(point) = heading(world, id) {
+			if target.distance(point) >= pos.distance(point) {
+				world.insert(id, Ai { behaviour: Behaviour::Idle });
+				world.insert(id, Velocity { vel: Vec2::zero() });
+				changed.push((id, Ai::kind()));
+				changed.push((id, Velocity::kind()));
+			}
+		}
+	}
+
+	changed
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	use entity::{EntityID, ComponentData, Position, Velocity, Body, Ai, Behaviour};
+	use map::Map;
+	use math::Vec2;
+	use world::World;
+
+	const TICK_MS: u64 = 50;
+
+	fn walker(behaviour: Behaviour) -> (World, EntityID) {
+		let mut world = World::new(Map::new(16, 32));
+		let id = world.spawn(vec![
+			ComponentData::Position(Position { pos: Vec2::new(100.0, 100.0) }),
+			ComponentData::Body(Body { size: Vec2::new(32.0, 48.0) }),
+			ComponentData::Ai(Ai { behaviour: behaviour })
+		]);
+		(world, id)
+	}
+
+	fn run(world: &mut World, ticks: usize) {
+		for _ in 0..ticks {
+			world.update(Duration::from_millis(TICK_MS));
+		}
+	}
+
+	#[test]
+	fn walks_onto_points_a_step_would_pass() {
+		//Just over ARRIVE_DISTANCE past a whole number of steps
+		let point = Vec2::new(100.0 + 2.18 + 4.8 * 7.0, 100.0);
+		let (mut world, id) = walker(Behaviour::Walk(vec![point]));
+
+		run(&mut world, 20);
+
+		assert_eq!(world.get::<Ai>(id).unwrap().behaviour, Behaviour::Idle);
+		assert!(world.get::<Position>(id).unwrap().pos.distance(point) < 1e-6);
+		assert!(world.get::<Velocity>(id).unwrap().vel.is_zero());
+	}
+
+	#[test]
+	fn patrols_keep_moving_between_points() {
+		let points = vec![Vec2::new(100.0, 100.0), Vec2::new(100.0 + 2.18 + 4.8 * 7.0, 100.0)];
+		let (mut world, id) = walker(Behaviour::Patrol(points.clone(), 1));
+		let mut visits = vec![0, 0];
+
+		for _ in 0..60 {
+			run(&mut world, 1);
+			let pos = world.get::<Position>(id).unwrap().pos;
+
+			for (i, &point) in points.iter().enumerate() {
+				if pos.distance(point) < 1e-6 {
+					visits[i] += 1;
+				}
+			}
+		}
+
+		assert!(visits.iter().all(|&count| count >= 2), "visits {:?}", visits);
+	}
+
+	#[test]
+	fn gives_up_walking_when_blocked() {
+		//Standing here would put the top of the body off the map
+		let point = Vec2::new(100.0, -30.0);
+		let (mut world, id) = walker(Behaviour::Walk(vec![point]));
+
+		run(&mut world, 100);
+
+		assert_eq!(world.get::<Ai>(id).unwrap().behaviour, Behaviour::Idle);
+		assert!(world.get::<Velocity>(id).unwrap().vel.is_zero());
+		assert!(world.get::<Position>(id).unwrap().pos.y >= 0.0);
+
+		//Nothing changes once it has stopped
+		assert!(world.update(Duration::from_millis(TICK_MS)).is_empty());
+	}
+}
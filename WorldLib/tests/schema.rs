@@ -0,0 +1,166 @@
+extern crate world_lib;
+
+use world_lib::{Map, World, Entity};
+use world_lib::entity::{EntityID, ComponentData, Position};
+use world_lib::math::Vec2;
+use world_lib::schema::{Kind, SchemaError, SCHEMA_VERSION};
+
+/**
+ * Documents as version 1 saved them, before envelopes existed
+ */
+const MAP: &'static str = include_str!("fixtures/map_v1.json");
+const WORLD: &'static str = include_str!("fixtures/world_v1.json");
+const ENTITY: &'static str = include_str!("fixtures/entity_v1.json");
+
+fn envelope(kind: Kind, version: u32, data: &str) -> String {
+	format!("{{\"kind\":\"{}\",\"version\":{},\"data\":{}}}", kind.name(), version, data.trim())
+}
+
+/**
+ * Both the bare document and the same document in a version 1 envelope
+ */
+fn both(kind: Kind, data: &str) -> Vec<String> {
+	vec![data.to_string(), envelope(kind, 1, data)]
+}
+
+fn id(index: u32) -> EntityID {
+	EntityID { index: index, generation: 0 }
+}
+
+fn check_map(map: &Map) {
+	assert_eq!((map.width, map.height), (3, 2));
+	assert_eq!(map.layers.len(), 1);
+	assert_eq!(map.layers[0].len(), 6);
+	assert_eq!(map.tileset.name, "grass");
+	assert!(map.registry.tiles.is_empty());
+	assert!(map.spawns.is_empty());
+	assert!(map.portals.is_empty());
+}
+
+/**
+ * A version 1 world whose entities have the given ids
+ */
+fn world_with_ids(ids: &[u64]) -> String {
+	let entities: Vec<String> = ids.iter().enumerate().map(|(i, id)| {
+		format!("{{\"id\":{},\"t\":\"Character\",\"pos\":[{}.0,0.0],\"size\":[1.0,1.0]}}", id, i * 100)
+	}).collect();
+
+	format!("{{\"map\":{},\"entities\":[{}]}}", MAP.trim(), entities.join(","))
+}
+
+#[test]
+fn loads_version_1_maps() {
+	for document in both(Kind::Map, MAP) {
+		match Map::from_json(&document) {
+			Ok(map) => check_map(&map),
+			Err(e) => panic!("{}, {:?}", document, e)
+		}
+	}
+}
+
+#[test]
+fn loads_version_1_worlds() {
+	for document in both(Kind::World, WORLD) {
+		let world = match World::from_json(&document) {
+			Ok(world) => world,
+			Err(e) => panic!("{}, {:?}", document, e)
+		};
+
+		check_map(&world.map);
+
+		//Ids 1 and 3 are packed down to the first two indices in the order they were saved
+		let mut ids = world.entities();
+		ids.sort_by_key(|id| id.index);
+		assert_eq!(ids, vec![id(0), id(1)]);
+
+		assert_eq!(world.get::<Position>(id(0)).map(|p| p.pos), Some(Vec2::new(10.0, 20.0)));
+		assert_eq!(world.get::<Position>(id(1)).map(|p| p.pos), Some(Vec2::new(50.0, 60.0)));
+		assert_eq!(world.near(Vec2::new(50.0, 60.0), 5.0), vec![id(1)]);
+	}
+}
+
+#[test]
+fn loads_version_1_entities() {
+	for document in both(Kind::Entity, ENTITY) {
+		let entity = match Entity::from_json(&document) {
+			Ok(entity) => entity,
+			Err(e) => panic!("{}, {:?}", document, e)
+		};
+
+		assert_eq!(entity.id, id(3));
+		assert_eq!(entity.components.len(), 3);
+
+		let position = entity.components.iter().filter_map(|component| match *component {
+			ComponentData::Position(ref position) => Some(position.pos),
+			_ => None
+		}).next();
+
+		assert_eq!(position, Some(Vec2::new(10.0, 20.0)));
+	}
+}
+
+#[test]
+fn packs_sparse_ids() {
+	let mut world = World::from_json(&world_with_ids(&[5, 1 << 40, 0])).unwrap();
+
+	let mut ids = world.entities();
+	ids.sort_by_key(|id| id.index);
+	assert_eq!(ids, vec![id(0), id(1), id(2)]);
+	assert_eq!(world.get::<Position>(id(1)).map(|p| p.pos), Some(Vec2::new(100.0, 0.0)));
+
+	//Nothing was left free, the next entity goes on the end
+	assert_eq!(world.spawn(Vec::new()), id(3));
+}
+
+#[test]
+fn refuses_duplicate_ids() {
+	match World::from_json(&world_with_ids(&[4, 7, 4])) {
+		Err(SchemaError::Malformed(1, "id")) => {},
+		other => panic!("expected Malformed, got {:?}", other.map(|_| ()))
+	}
+}
+
+#[test]
+fn upgraded_documents_round_trip() {
+	let map = Map::from_json(MAP).unwrap();
+	check_map(&Map::from_json(&map.as_json()).unwrap());
+
+	let world = World::from_json(WORLD).unwrap();
+	let reloaded = World::from_json(&world.as_json()).unwrap();
+	assert_eq!(reloaded.entities(), world.entities());
+	assert_eq!(reloaded.as_json(), world.as_json());
+
+	let entity = Entity::from_json(ENTITY).unwrap();
+	assert_eq!(Entity::from_json(&entity.as_json()).unwrap().as_json(), entity.as_json());
+}
+
+#[test]
+fn refuses_newer_versions() {
+	let map = envelope(Kind::Map, SCHEMA_VERSION + 1, MAP);
+
+	match Map::from_json(&map) {
+		Err(SchemaError::TooNew(version)) => assert_eq!(version, SCHEMA_VERSION + 1),
+		other => panic!("expected TooNew, got {:?}", other.map(|_| ()))
+	}
+}
+
+#[test]
+fn refuses_other_kinds() {
+	match World::from_json(&envelope(Kind::Map, 1, MAP)) {
+		Err(SchemaError::WrongKind(kind)) => assert_eq!(kind, "map"),
+		other => panic!("expected WrongKind, got {:?}", other.map(|_| ()))
+	}
+
+	match Map::from_json(&envelope(Kind::Entity, 1, ENTITY)) {
+		Err(SchemaError::WrongKind(kind)) => assert_eq!(kind, "entity"),
+		other => panic!("expected WrongKind, got {:?}", other.map(|_| ()))
+	}
+}
+
+#[test]
+fn refuses_unrecognised_documents() {
+	match Entity::from_json("{\"name\":\"nobody\"}") {
+		Err(SchemaError::Unrecognised) => {},
+		other => panic!("expected Unrecognised, got {:?}", other.map(|_| ()))
+	}
+}
@@ -0,0 +1,61 @@
+extern crate world_lib;
+
+use world_lib::Map;
+use world_lib::map::{Tile, Spawn, MapError};
+use world_lib::math::{Vec2, Rect};
+
+/**
+ * A small map saved from Tiled with an empty tile, a flipped tile, spawns and a portal
+ */
+const MAP: &'static str = include_str!("fixtures/tiled.json");
+
+fn check(map: &Map) {
+	assert_eq!((map.width, map.height), (4, 3));
+	assert_eq!((map.tile_width, map.tile_height), (64.0, 16.0));
+	assert_eq!((map.tileset.name.as_ref(), map.tileset.columns, map.tileset.count), ("grass", 4, 16));
+
+	assert_eq!(map.layers.len(), 2);
+	assert_eq!(map.layers[0][0], Some(Tile { x: 0, y: 0 }));
+	assert_eq!(map.layers[0][5], Some(Tile { x: 1, y: 1 }));
+	assert_eq!(map.layers[0][11], Some(Tile { x: 3, y: 2 }));
+
+	//Flipped in Tiled, which is dropped
+	assert_eq!(map.layers[0][6], Some(Tile { x: 2, y: 1 }));
+
+	assert_eq!(map.layers[1][5], Some(Tile { x: 3, y: 3 }));
+	assert_eq!(map.layers[1].iter().filter(|tile| tile.is_none()).count(), 11);
+
+	assert_eq!(map.spawns, vec![
+		Spawn { name: "start".to_string(), kind: "player".to_string(), pos: Vec2::new(96.0, 24.0) },
+		Spawn { name: "guard".to_string(), kind: "npc".to_string(), pos: Vec2::new(160.0, 40.0) }
+	]);
+
+	assert_eq!(map.portals.len(), 1);
+	assert_eq!(map.portals[0].area, Rect::new(Vec2::new(0.0, 8.0), Vec2::new(32.0, 16.0)));
+	assert_eq!(map.portals[0].zone, "cave");
+	assert_eq!(map.portals[0].target, Vec2::new(10.5, 20.0));
+}
+
+#[test]
+fn imports_maps_from_tiled() {
+	check(&Map::from_tiled(MAP).unwrap());
+}
+
+#[test]
+fn exported_maps_import_the_same() {
+	let map = Map::from_tiled(MAP).unwrap();
+	let exported = map.as_tiled().unwrap();
+	let imported = Map::from_tiled(&exported).unwrap();
+
+	check(&imported);
+	assert_eq!(imported.layers, map.layers);
+	assert_eq!(Map::from_tiled(&imported.as_tiled().unwrap()).unwrap().as_tiled().unwrap(), exported);
+}
+
+#[test]
+fn refuses_to_export_a_tileset_without_columns() {
+	let mut map = Map::new(4, 4);
+	map.tileset.columns = 0;
+
+	assert_eq!(map.as_tiled(), Err(MapError::NoColumns));
+}